@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
 
 fn parse_input(input: &str) -> Vec<u64> {
     input.lines()
@@ -6,59 +8,369 @@ fn parse_input(input: &str) -> Vec<u64> {
          .collect()
 }
 
-fn count_diff(input: &[u64]) -> (usize, usize, usize) {
-    let mut input = Vec::from(input);
-    input.sort();
-    input.insert(0, 0); // first element
-    input.push(input.last().unwrap() + 3); // device
-    let off_by_one = input.iter().skip(1);
-    input.iter()
-         .zip(off_by_one)
-         .map(|(a, b)| {
-             b - a 
-         })
-         .fold((0,0,0), |acc, diff| match diff {
-             1 => (acc.0+1, acc.1, acc.2),
-             2 => (acc.0, acc.1+1, acc.2),
-             3 => (acc.0, acc.1, acc.2+1),
-             x => panic!("unexpected diff {}", x),
-         })
+#[derive(Debug, Copy, Clone)]
+struct ChainConfig {
+    outlet : u64,
+    device_offset : u64,
+    max_gap : u64,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self { outlet: 0, device_offset: 3, max_gap: 3 }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AdapterError {
+    Gap { from: u64, to: u64 },
+    NotAboveOutlet(u64),
+    Repeated(u64),
+}
+
+impl fmt::Display for AdapterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdapterError::Gap { from, to } => write!(f, "no adapter bridges {} to {}", from, to),
+            AdapterError::NotAboveOutlet(x) => write!(f, "adapter {} is not above the outlet", x),
+            AdapterError::Repeated(x) => write!(f, "{} jolts appears twice, a link has to step up", x),
+        }
+    }
+}
+
+// sorted adapters with the outlet in front and the device at the back
+fn joltage_chain(input: &[u64], config: &ChainConfig) -> Result<Vec<u64>, AdapterError> {
+    let mut joltages = Vec::from(input);
+    joltages.sort();
+    if let Some(&lowest) = joltages.first().filter(|&&x| x <= config.outlet) {
+        return Err(AdapterError::NotAboveOutlet(lowest));
+    }
+    joltages.insert(0, config.outlet);
+    joltages.push(joltages.last().unwrap() + config.device_offset);
+    Ok(joltages)
+}
+
+// like joltage_chain, but every step has to be bridgeable by some adapter
+// and go up, so every rating in it is distinct
+fn bridged_chain(input: &[u64], config: &ChainConfig) -> Result<Vec<u64>, AdapterError> {
+    let joltages = joltage_chain(input, config)?;
+    for pair in joltages.windows(2) {
+        if pair[1] == pair[0] {
+            return Err(AdapterError::Repeated(pair[0]));
+        }
+        if pair[1] - pair[0] > config.max_gap {
+            return Err(AdapterError::Gap { from: pair[0], to: pair[1] });
+        }
+    }
+    Ok(joltages)
+}
+
+fn diff_histogram(input: &[u64], config: &ChainConfig) -> Result<BTreeMap<u64, usize>, AdapterError> {
+    let joltages = joltage_chain(input, config)?;
+    let mut histogram = BTreeMap::new();
+    for pair in joltages.windows(2) {
+        *histogram.entry(pair[1] - pair[0]).or_insert(0) += 1;
+    }
+    Ok(histogram)
 }
 
 fn find_result(input: &[u64]) -> usize {
-    let diffs = count_diff(input);
-    diffs.0 * diffs.2
-}
-
-type Memo = HashMap<u64, usize>;
-
-fn find_recursive(curr: u64, remaining: &[u64], memo: &mut Memo) -> usize {
-    if remaining.len() == 0 {
-        memo.insert(curr, 1);
-        1
-    } else {
-        match memo.get(&curr) {
-            Some(&x) => x,
-            None => {
-                let mut total = 0;
-                for (i, &num) in remaining.iter().enumerate() {
-                    if num - curr <= 3 {
-                        total += find_recursive(num, &remaining[i+1..], memo)
-                    } else {
-                        break
-                    }
+    let diffs = diff_histogram(input, &ChainConfig::default()).expect("adapters above outlet");
+    diffs.get(&1).unwrap_or(&0) * diffs.get(&3).unwrap_or(&0)
+}
+
+// with every rating distinct an adapter can be left out exactly when its
+// neighbours are close enough to connect directly
+struct JoltageReport {
+    joltages : Vec<u64>,
+    max_gap : u64,
+}
+
+impl JoltageReport {
+    fn new(input: &[u64], config: &ChainConfig) -> Result<Self, AdapterError> {
+        let joltages = bridged_chain(input, config)?;
+        Ok(Self { joltages, max_gap: config.max_gap })
+    }
+
+    fn is_mandatory(&self, i: usize) -> bool {
+        self.joltages[i + 1] - self.joltages[i - 1] > self.max_gap
+    }
+
+    fn mandatory(&self) -> Vec<u64> {
+        (1..self.joltages.len() - 1).filter(|&i| self.is_mandatory(i)).map(|i| self.joltages[i]).collect()
+    }
+
+    fn optional(&self) -> Vec<u64> {
+        (1..self.joltages.len() - 1).filter(|&i| !self.is_mandatory(i)).map(|i| self.joltages[i]).collect()
+    }
+}
+
+impl fmt::Display for JoltageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 1..self.joltages.len() - 1 {
+            let (prev, curr, next) = (self.joltages[i - 1], self.joltages[i], self.joltages[i + 1]);
+            if self.is_mandatory(i) {
+                writeln!(f, "{:>4} mandatory: {} to {} is a gap of {}", curr, prev, next, next - prev)?;
+            } else {
+                writeln!(f, "{:>4} optional: {} can connect straight to {}", curr, prev, next)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// little-endian base 10^9 digits without trailing zeros
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct BigUint {
+    digits : Vec<u32>,
+}
+
+impl BigUint {
+    const BASE : u32 = 1_000_000_000;
+
+    fn one() -> Self {
+        BigUint { digits: vec![1] }
+    }
+
+    fn add_assign(&mut self, other: &BigUint) {
+        let mut carry = 0;
+        for i in 0..self.digits.len().max(other.digits.len()) {
+            if i == self.digits.len() {
+                self.digits.push(0);
+            }
+            let sum = self.digits[i] + other.digits.get(i).unwrap_or(&0) + carry;
+            self.digits[i] = sum % Self::BASE;
+            carry = sum / Self::BASE;
+        }
+        if carry > 0 {
+            self.digits.push(carry);
+        }
+    }
+
+    // caller guarantees other <= self
+    fn sub_assign(&mut self, other: &BigUint) {
+        let mut borrow = 0;
+        for i in 0..self.digits.len() {
+            let sub = other.digits.get(i).unwrap_or(&0) + borrow;
+            if self.digits[i] >= sub {
+                self.digits[i] -= sub;
+                borrow = 0;
+            } else {
+                self.digits[i] = self.digits[i] + Self::BASE - sub;
+                borrow = 1;
+            }
+        }
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+    }
+
+    // uniform in 0..self by rejection, at most half the draws are thrown away
+    fn random_below(&self, rng: &mut Rng) -> BigUint {
+        let (&top, _) = self.digits.split_last().expect("non-zero bound");
+        loop {
+            let mut digits : Vec<u32> = (1..self.digits.len()).map(|_| rng.below(Self::BASE)).collect();
+            digits.push(rng.below(top + 1));
+            while digits.last() == Some(&0) {
+                digits.pop();
+            }
+            let candidate = BigUint { digits };
+            if candidate < *self {
+                break candidate;
+            }
+        }
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(mut n: u64) -> BigUint {
+        let mut digits = vec![];
+        while n > 0 {
+            digits.push((n % Self::BASE as u64) as u32);
+            n /= Self::BASE as u64;
+        }
+        BigUint { digits }
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.digits.len()
+            .cmp(&other.digits.len())
+            .then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.digits.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                for digit in rest.iter().rev() {
+                    write!(f, "{:09}", digit)?;
                 }
-                memo.insert(curr, total);
-                total
-            },
+                Ok(())
+            }
+        }
+    }
+}
+
+fn count_arrangements(input: &[u64], config: &ChainConfig) -> Result<BigUint, AdapterError> {
+    Ok(ChainSpace::new(input, config)?.count().clone())
+}
+
+// xorshift64*, plenty for picking sample chains
+struct Rng(u64);
+
+impl Rng {
+    fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_nanos() as u64);
+        Rng(nanos | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: u32) -> u32 {
+        let zone = u64::MAX - u64::MAX % bound as u64;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                break (x % bound as u64) as u32;
+            }
         }
     }
 }
 
-fn find_all_combinations(input: &[u64]) -> usize {
-    let mut input = Vec::from(input);
-    input.sort();
-    find_recursive(0, &input, &mut HashMap::new())
+// every valid chain as the adapters it uses, ordered lexicographically;
+// bridged_chain keeps the ratings distinct, so each index path is a
+// different chain; ways_from[i] counts the ways to finish one from
+// joltages[i]
+struct ChainSpace {
+    joltages : Vec<u64>,
+    max_gap : u64,
+    ways_from : Vec<BigUint>,
+}
+
+impl ChainSpace {
+    fn new(input: &[u64], config: &ChainConfig) -> Result<Self, AdapterError> {
+        let joltages = bridged_chain(input, config)?;
+        let last = joltages.len() - 1;
+        let mut ways_from = vec![BigUint::default(); joltages.len()];
+        ways_from[last] = BigUint::one();
+        for i in (0..last).rev() {
+            let mut total = BigUint::default();
+            for j in (i + 1..=last).take_while(|&j| joltages[j] - joltages[i] <= config.max_gap) {
+                total.add_assign(&ways_from[j]);
+            }
+            ways_from[i] = total;
+        }
+        Ok(Self { joltages, max_gap: config.max_gap, ways_from })
+    }
+
+    fn count(&self) -> &BigUint {
+        &self.ways_from[0]
+    }
+
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        to < self.joltages.len() && self.joltages[to] - self.joltages[from] <= self.max_gap
+    }
+
+    fn adapters(&self, path: &[usize]) -> Vec<u64> {
+        path[1..path.len() - 1].iter().map(|&i| self.joltages[i]).collect()
+    }
+
+    // stopping at the device sorts before any longer chain, so it is tried
+    // first whenever it is in reach; the gap check guarantees from + 1 is
+    fn first_step(&self, from: usize) -> usize {
+        let last = self.joltages.len() - 1;
+        if self.reaches(from, last) { last } else { from + 1 }
+    }
+
+    fn next_step(&self, from: usize, step: usize) -> Option<usize> {
+        let last = self.joltages.len() - 1;
+        let next = if step == last { from + 1 } else { step + 1 };
+        Some(next).filter(|&n| n < last && self.reaches(from, n))
+    }
+
+    fn fill(&self, path: &mut Vec<usize>) {
+        let last = self.joltages.len() - 1;
+        while let Some(&curr) = path.last().filter(|&&c| c != last) {
+            path.push(self.first_step(curr));
+        }
+    }
+
+    fn iter(&self) -> Chains<'_> {
+        let mut path = vec![0];
+        self.fill(&mut path);
+        Chains { space: self, path: Some(path) }
+    }
+
+    // walk down from the outlet, skipping whole subtrees of chains that
+    // come before k
+    fn nth_chain(&self, k: &BigUint) -> Option<Vec<u64>> {
+        if k >= self.count() {
+            return None;
+        }
+        let mut k = k.clone();
+        let last = self.joltages.len() - 1;
+        let mut path = vec![0];
+        let mut curr = 0;
+        while curr != last {
+            let mut next = self.first_step(curr);
+            while k >= self.ways_from[next] {
+                k.sub_assign(&self.ways_from[next]);
+                next = self.next_step(curr, next).expect("k below the count");
+            }
+            path.push(next);
+            curr = next;
+        }
+        Some(self.adapters(&path))
+    }
+
+    fn sample(&self, rng: &mut Rng) -> Vec<u64> {
+        self.nth_chain(&self.count().random_below(rng)).unwrap()
+    }
+}
+
+struct Chains<'a> {
+    space : &'a ChainSpace,
+    path : Option<Vec<usize>>,
+}
+
+impl<'a> Iterator for Chains<'a> {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Vec<u64>> {
+        let mut path = self.path.take()?;
+        let chain = self.space.adapters(&path);
+
+        // bump the deepest step that has a later alternative, then fill in
+        // the first steps after it again
+        for k in (1..path.len()).rev() {
+            if let Some(next) = self.space.next_step(path[k - 1], path[k]) {
+                path.truncate(k);
+                path.push(next);
+                self.space.fill(&mut path);
+                self.path = Some(path);
+                break;
+            }
+        }
+        Some(chain)
+    }
 }
 
 fn main() {
@@ -110,15 +422,72 @@ fn main() {
     3";
     let test_input2 = parse_input(&test_input2);
 
-    println!("{:?}", count_diff(&test_input));
-    println!("{:?}", count_diff(&test_input2));
+    let config = ChainConfig::default();
+    println!("{:?}", diff_histogram(&test_input, &config).expect("adapters above outlet"));
+    println!("{:?}", diff_histogram(&test_input2, &config).expect("adapters above outlet"));
 
     let real_input = parse_input(&include_str!("day10.txt"));
-    println!("counts: {:?}", count_diff(&real_input));
+    println!("counts: {:?}", diff_histogram(&real_input, &config).expect("adapters above outlet"));
     println!("{:?}", find_result(&real_input));
 
-    println!("combination count of test {:?}", find_all_combinations(&test_input));
-    println!("combination count of test2 {:?}", find_all_combinations(&test_input2));
-    println!("combination count of real {:?}", find_all_combinations(&real_input));
+    println!("combination count of test {}", count_arrangements(&test_input, &config).expect("bridgeable"));
+    println!("combination count of test2 {}", count_arrangements(&test_input2, &config).expect("bridgeable"));
+    println!("combination count of real {}", count_arrangements(&real_input, &config).expect("bridgeable"));
+
+    let long_chain : Vec<u64> = (1..=400).collect();
+    println!("combination count of 1 to 400 {}", count_arrangements(&long_chain, &config).expect("bridgeable"));
+    match count_arrangements(&test_input, &ChainConfig { max_gap: 2, ..config }) {
+        Ok(count) => println!("combination count of test with gap 2 {}", count),
+        Err(e) => println!("{}", e),
+    }
+
+    let report = JoltageReport::new(&test_input, &config).expect("bridgeable");
+    print!("{}", report);
+    println!("mandatory {:?}, optional {:?}", report.mandatory(), report.optional());
+    // either 2 could be dropped alone, but never both, so there is no report
+    assert_eq!(JoltageReport::new(&[2, 2, 5], &config).err(), Some(AdapterError::Repeated(2)));
+
+    let shifted : Vec<u64> = test_input.iter().map(|x| x + 2).collect();
+    let offset_config = ChainConfig { outlet: 2, device_offset: 1, ..config };
+    println!("shifted test counts from a 2 jolt outlet to a +1 device {:?}",
+             diff_histogram(&shifted, &offset_config).expect("adapters above outlet"));
+    println!("combination count of shifted test from a 2 jolt outlet {}",
+             count_arrangements(&shifted, &offset_config).expect("bridgeable"));
+    assert_eq!(count_arrangements(&test_input, &ChainConfig { outlet: 1, ..config }),
+               Err(AdapterError::NotAboveOutlet(1)));
+    assert_eq!(count_arrangements(&[1, 1], &config), Err(AdapterError::Repeated(1)));
+    assert_eq!(count_arrangements(&[1], &ChainConfig { device_offset: 0, ..config }), Err(AdapterError::Repeated(1)));
 
-}
\ No newline at end of file
+    let space = ChainSpace::new(&test_input, &config).expect("bridgeable");
+    let all : Vec<Vec<u64>> = space.iter().collect();
+    println!("test has {} chains, enumerated {}", space.count(), all.len());
+    for (k, chain) in all.iter().enumerate() {
+        assert_eq!(space.nth_chain(&BigUint::from(k as u64)).as_ref(), Some(chain));
+    }
+    println!("first test chain {:?}", all[0]);
+    println!("last test chain {:?}", all[all.len() - 1]);
+
+    // a device closer than max_gap lets chains stop early
+    for (input, config) in [(&shifted, offset_config), (&vec![1, 2], ChainConfig { device_offset: 1, ..config })] {
+        let space = ChainSpace::new(input, &config).expect("bridgeable");
+        let chains : Vec<Vec<u64>> = space.iter().collect();
+        let mut sorted = chains.clone();
+        sorted.sort();
+        assert_eq!(chains, sorted);
+        assert_eq!(*space.count(), BigUint::from(chains.len() as u64));
+        for (k, chain) in chains.iter().enumerate() {
+            assert_eq!(space.nth_chain(&BigUint::from(k as u64)).as_ref(), Some(chain));
+        }
+        println!("{} chains with {:?}, first {:?}", chains.len(), config, chains[0]);
+    }
+
+    assert_eq!(ChainSpace::new(&[1, 1], &config).err(), Some(AdapterError::Repeated(1)));
+
+    let mut rng = Rng::from_time();
+    let real_space = ChainSpace::new(&real_input, &config).expect("bridgeable");
+    println!("first three real chains {:?}", real_space.iter().take(3).collect::<Vec<_>>());
+    println!("real chain 1000 {:?}", real_space.nth_chain(&BigUint::from(1000)));
+    println!("random real chain {:?}", real_space.sample(&mut rng));
+    let long_space = ChainSpace::new(&long_chain, &config).expect("bridgeable");
+    println!("random chain of 1 to 400 uses {} adapters", long_space.sample(&mut rng).len());
+}
@@ -1,97 +1,103 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum Seat {
     Empty,
     Occupied,
     Floor,
 }
 
-fn parse_line(line: &str) -> Vec<Seat> {
+#[derive(Debug, PartialEq)]
+enum LayoutError {
+    BadCharacter { row: usize, col: usize, c: char },
+    Ragged { row: usize, width: usize, expected: usize },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::BadCharacter { row, col, c } =>
+                write!(f, "weird character {:?} at row {} column {}", c, row + 1, col + 1),
+            LayoutError::Ragged { row, width, expected } =>
+                write!(f, "row {} is {} seats wide but row 1 is {}", row + 1, width, expected),
+        }
+    }
+}
+
+fn parse_line(row: usize, line: &str) -> Result<Vec<Seat>, LayoutError> {
     let line = line.trim();
     line.chars()
-        .map(|c| match c {
-            'L' => Seat::Empty,
-            '.' => Seat::Floor,
-            '#' => Seat::Occupied,
-            x   => panic!("weird character {:?}", x)
+        .enumerate()
+        .map(|(col, c)| match c {
+            'L' => Ok(Seat::Empty),
+            '.' => Ok(Seat::Floor),
+            '#' => Ok(Seat::Occupied),
+            c   => Err(LayoutError::BadCharacter { row, col, c }),
         })
         .collect()
 }
 
+// seats are stored row-major, back is the buffer the next generation is
+// written into before the two are swapped
 struct State {
-    seats : Vec<Vec<Seat>>,
+    seats : Vec<Seat>,
+    back : Vec<Seat>,
+    width : usize,
+    height : usize,
 }
 
 impl State {
-    fn new(lines: &str) -> Self {
-        let seats = lines.lines()
-                         .map(|s| parse_line(s))
-                         .collect();
-        Self { seats }
-    }
-
-    fn run_step(&mut self) {
-        let mut new_seats = self.seats.clone();
-        for i in 0..self.seats.len() {
-            for j in 0..self.seats.len() {
-                new_seats[i][j] = self.get_new_seat(i, j);
-            }
-        }
-        self.seats = new_seats;
+    fn parse_rows(lines: &str) -> Result<Vec<Vec<Seat>>, LayoutError> {
+        lines.lines()
+             .enumerate()
+             .map(|(row, line)| parse_line(row, line))
+             .collect()
     }
 
-    fn run_step_new(&mut self) {
-        let mut new_seats = self.seats.clone();
-        for i in 0..self.seats.len() {
-            for j in 0..self.seats.len() {
-                new_seats[i][j] = self.get_new_seat_mod(i, j);
-            }
-        }
-        self.seats = new_seats;
+    fn from_rows(rows: Vec<Vec<Seat>>, width: usize) -> Self {
+        let height = rows.len();
+        let seats : Vec<Seat> = rows.into_iter().flatten().collect();
+        let back = seats.clone();
+        Self { seats, back, width, height }
     }
 
-    fn run_until_stable(&mut self) {
-        loop {
-            let old_seats = self.seats.clone();
-            self.run_step();
-            let old_flat = old_seats.iter().flat_map(|r| r.iter());
-            let new_flat = self.seats.iter().flat_map(|r| r.iter());
-            if old_flat.eq(new_flat) { 
-                break;
-            }
+    // every row has to be as wide as the first
+    fn new(lines: &str) -> Result<Self, LayoutError> {
+        let rows = Self::parse_rows(lines)?;
+        let width = rows.first().map_or(0, |r| r.len());
+        if let Some((row, r)) = rows.iter().enumerate().find(|(_, r)| r.len() != width) {
+            return Err(LayoutError::Ragged { row, width: r.len(), expected: width });
         }
+        Ok(Self::from_rows(rows, width))
     }
 
-    fn run_until_stable_new(&mut self) {
-        loop {
-            let old_seats = self.seats.clone();
-            self.run_step_new();
-            let old_flat = old_seats.iter().flat_map(|r| r.iter());
-            let new_flat = self.seats.iter().flat_map(|r| r.iter());
-            if old_flat.eq(new_flat) { 
-                break;
-            }
+    // short rows are filled out with floor up to the widest row
+    fn padded(lines: &str) -> Result<Self, LayoutError> {
+        let mut rows = Self::parse_rows(lines)?;
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        for row in rows.iter_mut() {
+            row.resize(width, Seat::Floor);
         }
+        Ok(Self::from_rows(rows, width))
     }
 
-    fn find_result(&mut self) -> usize {
-        self.run_until_stable();
-        self.seats.iter()
-                  .flat_map(|r| r.iter())
-                  .filter(|s| if let Seat::Occupied = s { true } else { false })
-                  .count()
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.seats.hash(&mut hasher);
+        hasher.finish()
     }
 
-    fn find_result_new(&mut self) -> usize {
-        self.run_until_stable_new();
+    fn occupied(&self) -> usize {
         self.seats.iter()
-                  .flat_map(|r| r.iter())
-                  .filter(|s| if let Seat::Occupied = s { true } else { false })
+                  .filter(|&&s| s == Seat::Occupied)
                   .count()
     }
 
-    fn get_adjacent(&self, i: usize, j: usize) -> Vec<Seat> {
+    fn get_adjacent(&self, i: usize, j: usize) -> Vec<usize> {
         let mut seats = vec![];
         for i_off in -1..=1 {
             for j_off in -1..=1 {
@@ -103,90 +109,259 @@ impl State {
                 }
                 let other_i = (i as i64 + i_off) as usize;
                 let other_j = (j as i64 + j_off) as usize;
-                if other_i < self.seats.len() && other_j < self.seats[i].len() {
-                    seats.push(self.seats[other_i][other_j]);
+                if other_i < self.height && other_j < self.width {
+                    seats.push(other_i * self.width + other_j);
                 }
             }
         }
         seats
     }
 
-    fn get_far_adjacent(&self, i: usize, j: usize) -> Vec<Seat> {
+    // floor never changes, so the first seat seen in each direction is the
+    // same for every generation
+    fn get_far_adjacent(&self, i: usize, j: usize) -> Vec<usize> {
         let mut seats = vec![];
-        let directions = vec![(-1,-1), (-1,0), (-1,1), (0,-1), (0,1), (1,-1), (1,0), (1,1)];
-        for direction in directions {
+        let directions = [(-1,-1), (-1,0), (-1,1), (0,-1), (0,1), (1,-1), (1,0), (1,1)];
+        for direction in directions.iter() {
             let mut curr_i = (i as i64) + direction.0;
             let mut curr_j = (j as i64) + direction.1;
-            let seat_len = self.seats.len() as i64;
-            while curr_i >= 0 && curr_i < seat_len &&
-                  curr_j >= 0 && curr_j < seat_len {
-                
-                match self.seats[curr_i as usize][curr_j as usize] {
-                    Seat::Floor => (),
-                    Seat::Empty => {
-                        seats.push(Seat::Empty);
-                        break;
-                    },
-                    Seat::Occupied => {
-                        seats.push(Seat::Occupied);
-                        break;
-                    },
-                };
-                curr_i = curr_i + direction.0;
-                curr_j = curr_j + direction.1;
+            while curr_i >= 0 && curr_i < self.height as i64 &&
+                  curr_j >= 0 && curr_j < self.width as i64 {
+                let idx = curr_i as usize * self.width + curr_j as usize;
+                if self.seats[idx] != Seat::Floor {
+                    seats.push(idx);
+                    break;
+                }
+                curr_i += direction.0;
+                curr_j += direction.1;
             }
         }
         seats
     }
+}
 
-    fn get_new_seat(&mut self, i: usize, j: usize) -> Seat {
-        match self.seats[i][j] {
-            Seat::Empty => {
-                let occupied = self.get_adjacent(i, j).iter().filter(|s| if let Seat::Occupied = s { true } else { false }).count();
-                if occupied == 0 {
-                    Seat::Occupied
-                } else {
-                    Seat::Empty
-                }
-            },
-            Seat::Occupied => {
-                let occupied = self.get_adjacent(i, j).iter().filter(|s| if let Seat::Occupied = s { true } else { false }).count();
-                if occupied >= 4 {
-                    Seat::Empty
-                } else {
-                    Seat::Occupied
-                }
+type Neighbourhood = fn(&State, usize, usize) -> Vec<usize>;
+
+// a seating rule is which seats a seat looks at and how it reacts to the
+// number of them that are occupied
+struct Automaton<N, T> {
+    neighbourhood : N,
+    transition : T,
+}
+
+impl<N, T> Automaton<N, T>
+where
+    N: Fn(&State, usize, usize) -> Vec<usize>,
+    T: Fn(Seat, usize) -> Seat,
+{
+    fn new(neighbourhood: N, transition: T) -> Self {
+        Self { neighbourhood, transition }
+    }
+
+    // only depends on the layout, so build it once and reuse it every step
+    fn neighbour_table(&self, state: &State) -> Vec<Vec<usize>> {
+        (0..state.seats.len())
+            .map(|idx| match state.seats[idx] {
+                Seat::Floor => vec![],
+                _ => (self.neighbourhood)(state, idx / state.width, idx % state.width),
+            })
+            .collect()
+    }
+
+    // true if any seat changed
+    fn run_step(&self, table: &[Vec<usize>], state: &mut State) -> bool {
+        let mut changed = false;
+        for (idx, neighbours) in table.iter().enumerate() {
+            let seat = state.seats[idx];
+            let occupied = neighbours.iter().filter(|&&n| state.seats[n] == Seat::Occupied).count();
+            let next = (self.transition)(seat, occupied);
+            changed |= next != seat;
+            state.back[idx] = next;
+        }
+        std::mem::swap(&mut state.seats, &mut state.back);
+        changed
+    }
+
+    // calls on_generation with the starting layout and every layout after
+    // it until one repeats or max_generations have run; fingerprints only
+    // narrow down the candidates, a repeat is confirmed on the stored grid
+    fn run<F: FnMut(usize, &State)>(&self, state: &mut State, max_generations: usize, mut on_generation: F) -> Outcome {
+        let table = self.neighbour_table(state);
+        let mut grids = vec![state.seats.clone()];
+        let mut seen : HashMap<u64, Vec<usize>> = HashMap::new();
+        seen.entry(state.fingerprint()).or_default().push(0);
+        on_generation(0, state);
+        for generation in 1..=max_generations {
+            if !self.run_step(&table, state) {
+                return Outcome::Stable { generations: generation - 1 };
+            }
+            on_generation(generation, state);
+            let candidates = seen.entry(state.fingerprint()).or_default();
+            if let Some(&first) = candidates.iter().find(|&&g| grids[g] == state.seats) {
+                return Outcome::Cycle { generations: generation, period: generation - first };
             }
-            Seat::Floor => Seat::Floor,
+            candidates.push(generation);
+            grids.push(state.seats.clone());
         }
+        Outcome::CapReached { generations: max_generations }
     }
 
-    fn get_new_seat_mod(&mut self, i: usize, j: usize) -> Seat {
-        match self.seats[i][j] {
-            Seat::Empty => {
-                let occupied = self.get_far_adjacent(i, j).iter().filter(|s| if let Seat::Occupied = s { true } else { false }).count();
-                if occupied == 0 {
-                    Seat::Occupied
-                } else {
-                    Seat::Empty
-                }
-            },
-            Seat::Occupied => {
-                let occupied = self.get_far_adjacent(i, j).iter().filter(|s| if let Seat::Occupied = s { true } else { false }).count();
-                if occupied >= 5 {
-                    Seat::Empty
-                } else {
-                    Seat::Occupied
-                }
+    fn run_until_stable(&self, state: &mut State, max_generations: usize) -> Outcome {
+        self.run(state, max_generations, |_, _| ())
+    }
+
+    // None unless the layout settles down
+    fn find_result(&self, state: &mut State) -> Option<usize> {
+        match self.run_until_stable(state, MAX_GENERATIONS) {
+            Outcome::Stable { .. } => Some(state.occupied()),
+            _ => None,
+        }
+    }
+
+    fn frames(&self, state: &mut State, max_generations: usize) -> (Vec<Frame>, Outcome) {
+        let mut frames = vec![];
+        let outcome = self.run(state, max_generations, |generation, state| {
+            frames.push(Frame::capture(generation, state))
+        });
+        (frames, outcome)
+    }
+}
+
+const MAX_GENERATIONS : usize = 10_000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Outcome {
+    Stable { generations: usize },
+    Cycle { generations: usize, period: usize },
+    CapReached { generations: usize },
+}
+
+struct Frame {
+    generation : usize,
+    occupied : usize,
+    width : usize,
+    seats : Vec<Seat>,
+}
+
+impl Frame {
+    fn capture(generation: usize, state: &State) -> Self {
+        Self { generation, occupied: state.occupied(), width: state.width, seats: state.seats.clone() }
+    }
+
+    fn height(&self) -> usize {
+        self.seats.len().checked_div(self.width).unwrap_or(0)
+    }
+
+    fn rgb(seat: Seat) -> [u8; 3] {
+        match seat {
+            Seat::Floor => [32, 32, 32],
+            Seat::Empty => [70, 130, 180],
+            Seat::Occupied => [220, 80, 60],
+        }
+    }
+
+    // one scale x scale block of pixels per seat, the generation and
+    // occupancy go in a text chunk
+    fn write_png<W: Write>(&self, out: &mut W, scale: usize) -> io::Result<()> {
+        let (width, height) = (self.width * scale, self.height() * scale);
+        let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+        for row in 0..height {
+            raw.push(0); // no filter
+            for col in 0..width {
+                let seat = self.seats[(row / scale) * self.width + col / scale];
+                raw.extend_from_slice(&Self::rgb(seat));
             }
-            Seat::Floor => Seat::Floor,
         }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bit rgb
+        let comment = format!("Comment\0generation {}, {} occupied", self.generation, self.occupied);
+
+        out.write_all(b"\x89PNG\r\n\x1a\n")?;
+        write_png_chunk(out, b"IHDR", &header)?;
+        write_png_chunk(out, b"tEXt", comment.as_bytes())?;
+        write_png_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+        write_png_chunk(out, b"IEND", &[])
+    }
+
+    fn write_ansi<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "\x1b[2J\x1b[Hgeneration {}, {} occupied", self.generation, self.occupied)?;
+        for row in self.seats.chunks(self.width.max(1)) {
+            for &seat in row {
+                let [r, g, b] = Self::rgb(seat);
+                write!(out, "\x1b[48;2;{};{};{}m  ", r, g, b)?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        out.flush()
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn write_png_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut body = kind.to_vec();
+    body.extend_from_slice(data);
+    out.write_all(&body)?;
+    out.write_all(&crc32(&body).to_be_bytes())
+}
+
+// a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
     }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+fn seating(tolerance: usize) -> impl Fn(Seat, usize) -> Seat {
+    move |seat, occupied| match seat {
+        Seat::Empty if occupied == 0 => Seat::Occupied,
+        Seat::Occupied if occupied >= tolerance => Seat::Empty,
+        other => other,
+    }
+}
+
+fn adjacent_rule() -> Automaton<Neighbourhood, impl Fn(Seat, usize) -> Seat> {
+    Automaton::new(State::get_adjacent, seating(4))
+}
+
+fn line_of_sight_rule() -> Automaton<Neighbourhood, impl Fn(Seat, usize) -> Seat> {
+    Automaton::new(State::get_far_adjacent, seating(5))
 }
 
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for row in self.seats.iter() {
+        if self.width == 0 {
+            return Ok(());
+        }
+        for row in self.seats.chunks(self.width) {
             let mut row : String =
                 row.iter()
                    .map( |s| match s {
@@ -217,26 +392,78 @@ fn main() {
     L.LLLLLL.L
     L.LLLLL.LL";
 
-    let mut test_state = State::new(test_input);
+    let mut test_state = State::new(test_input).expect("valid layout");
     print!("{}", test_state);
+    let rule = line_of_sight_rule();
+    let table = rule.neighbour_table(&test_state);
     for i in 0..8 {
-        test_state.run_step_new();
+        rule.run_step(&table, &mut test_state);
         println!("{}", i);
         print!("{}", test_state);
     }
 
-    let mut test_state = State::new(test_input);
-    println!("result for test is {}", test_state.find_result());
+    let mut test_state = State::new(test_input).expect("valid layout");
+    println!("result for test is {}", adjacent_rule().find_result(&mut test_state).expect("stabilises"));
 
     let real_input = include_str!("day11.txt");
 
-    let mut real_state = State::new(&real_input);
-    println!("result for real is {}", real_state.find_result());
+    let mut real_state = State::new(&real_input).expect("valid layout");
+    println!("result for real is {}", adjacent_rule().find_result(&mut real_state).expect("stabilises"));
+
+    let mut test_state = State::new(test_input).expect("valid layout");
+    println!("result for test new is {}", line_of_sight_rule().find_result(&mut test_state).expect("stabilises"));
+    let mut real_state = State::new(&real_input).expect("valid layout");
+    println!("result for real is {}", line_of_sight_rule().find_result(&mut real_state).expect("stabilises"));
+
+    let wide_input = "\
+    L.LL.LL.LL.LL
+    LLLLLLL.LLLLL
+    L.L.L..L..L.L";
+    let mut wide_state = State::new(wide_input).expect("valid layout");
+    let wide_result = line_of_sight_rule().find_result(&mut wide_state).expect("stabilises");
+    println!("result for {}x{} is {}", wide_state.width, wide_state.height, wide_result);
 
-    let mut test_state = State::new(test_input);
-    println!("result for test new is {}", test_state.find_result_new());
-    let mut real_state = State::new(&real_input);
-    println!("result for real is {}", real_state.find_result_new());
+    let ragged_input = "\
+    L.LL
+    LLLLLL
+    L.L";
+    if let Err(e) = State::new(ragged_input) {
+        println!("{}", e);
+    }
+    let mut ragged_state = State::padded(ragged_input).expect("valid layout");
+    println!("result for padded ragged layout is {}", adjacent_rule().find_result(&mut ragged_state).expect("stabilises"));
+    print!("{}", ragged_state);
 
+    let mut state = State::new(test_input).expect("valid layout");
+    println!("part 2 rule on test {:?}", line_of_sight_rule().run_until_stable(&mut state, MAX_GENERATIONS));
+    let restless = Automaton::new(State::get_adjacent, seating(1));
+    let mut state = State::new(test_input).expect("valid layout");
+    println!("intolerant rule on test {:?}", restless.run_until_stable(&mut state, MAX_GENERATIONS));
+    let mut state = State::new(test_input).expect("valid layout");
+    println!("part 1 rule on test capped at 3 {:?}", adjacent_rule().run_until_stable(&mut state, 3));
 
-}
\ No newline at end of file
+    match std::env::args().nth(1).as_deref() {
+        Some("png") => {
+            let mut state = State::new(real_input).expect("valid layout");
+            std::fs::create_dir_all("day11_frames").expect("create frame directory");
+            let (frames, outcome) = line_of_sight_rule().frames(&mut state, MAX_GENERATIONS);
+            println!("{:?}", outcome);
+            for frame in frames {
+                let path = format!("day11_frames/{:04}.png", frame.generation);
+                let mut file = io::BufWriter::new(std::fs::File::create(&path).expect("create frame"));
+                frame.write_png(&mut file, 4).expect("write frame");
+                println!("{}: generation {}, {} occupied", path, frame.generation, frame.occupied);
+            }
+        },
+        Some("ansi") => {
+            let mut state = State::new(real_input).expect("valid layout");
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            for frame in line_of_sight_rule().frames(&mut state, MAX_GENERATIONS).0 {
+                frame.write_ansi(&mut out).expect("terminal output");
+                std::thread::sleep(std::time::Duration::from_millis(150));
+            }
+        },
+        _ => (),
+    }
+}
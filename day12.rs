@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, Copy, Clone)]
 enum Action {
     North(i32),
@@ -33,103 +35,520 @@ impl Action {
     }
 }
 
-#[derive(Debug)]
-struct StatePart1 {
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::North(v) => write!(f, "N{}", v),
+            Action::South(v) => write!(f, "S{}", v),
+            Action::East(v) => write!(f, "E{}", v),
+            Action::West(v) => write!(f, "W{}", v),
+            Action::Left(a) => write!(f, "L{}", a),
+            Action::Right(a) => write!(f, "R{}", a),
+            Action::Forward(v) => write!(f, "F{}", v),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum NavError {
+    UnsupportedTurn(i32),
+    TooFar(i64),
+}
+
+impl fmt::Display for NavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavError::UnsupportedTurn(a) => write!(f, "can't turn {} degrees on a grid", a),
+            NavError::TooFar(v) => write!(f, "{} is too far for a single action", v),
+        }
+    }
+}
+
+// Exact only turns in quarter turns and never loses precision, Approx
+// turns by any angle in floating point
+trait Vector: Copy + fmt::Debug {
+    type Scalar: fmt::Display + Copy;
+
+    fn new(x: i32, y: i32) -> Self;
+    fn add(self, other: Self) -> Self;
+    fn scale(self, k: i32) -> Self;
+    fn rotate(self, degrees: i32) -> Result<Self, NavError>;
+    fn manhattan(self) -> Self::Scalar;
+    fn coords(self) -> (Self::Scalar, Self::Scalar);
+    fn to_f64(self) -> (f64, f64);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Exact {
+    x : i64,
+    y : i64,
+}
+
+impl Vector for Exact {
+    type Scalar = i64;
+
+    fn new(x: i32, y: i32) -> Self {
+        Exact { x: x as i64, y: y as i64 }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Exact { x: self.x + other.x, y: self.y + other.y }
+    }
+
+    fn scale(self, k: i32) -> Self {
+        Exact { x: self.x * k as i64, y: self.y * k as i64 }
+    }
+
+    fn rotate(self, degrees: i32) -> Result<Self, NavError> {
+        if degrees % 90 != 0 {
+            return Err(NavError::UnsupportedTurn(degrees));
+        }
+        Ok(match degrees.rem_euclid(360) / 90 {
+            0 => self,
+            1 => Exact { x: -self.y, y: self.x },
+            2 => Exact { x: -self.x, y: -self.y },
+            _ => Exact { x: self.y, y: -self.x },
+        })
+    }
+
+    fn manhattan(self) -> i64 {
+        self.x.abs() + self.y.abs()
+    }
+
+    fn coords(self) -> (i64, i64) {
+        (self.x, self.y)
+    }
+
+    fn to_f64(self) -> (f64, f64) {
+        (self.x as f64, self.y as f64)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Approx {
     x : f64,
     y : f64,
-    angle : f64
 }
 
-impl StatePart1 {
-    fn new() -> Self {
-        Self { x: 0.0, y: 0.0, angle: 0.0 }
+impl Vector for Approx {
+    type Scalar = f64;
+
+    fn new(x: i32, y: i32) -> Self {
+        Approx { x: x as f64, y: y as f64 }
     }
 
-    fn take_action(&mut self, action : Action) {
-        match action {
-            Action::North(v) => self.y += v as f64,
-            Action::South(v) => self.y -= v as f64,
-            Action::East(v) => self.x += v as f64,
-            Action::West(v) => self.x -= v as f64,
-            Action::Left(a) => self.angle += a as f64,
-            Action::Right(a) => self.angle -= a as f64,
-            Action::Forward(v) => {
-                let (sin, cos) = self.angle.to_radians().sin_cos();
-                self.x += (v as f64) * cos;
-                self.y += (v as f64) * sin;
-            }
-        };
+    fn add(self, other: Self) -> Self {
+        Approx { x: self.x + other.x, y: self.y + other.y }
+    }
+
+    fn scale(self, k: i32) -> Self {
+        Approx { x: self.x * k as f64, y: self.y * k as f64 }
     }
 
-    fn from_instructions(instructions: &[Action]) -> Self {
+    fn rotate(self, degrees: i32) -> Result<Self, NavError> {
+        let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+        Ok(Approx {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+        })
+    }
+
+    fn manhattan(self) -> f64 {
+        self.x.abs() + self.y.abs()
+    }
+
+    fn coords(self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+
+    fn to_f64(self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+}
+
+// where the ship (and its waypoint, if it has one) ended up after each
+// action, starting with where it began
+#[derive(Debug)]
+struct Step<V> {
+    action : Option<Action>,
+    ship : V,
+    waypoint : Option<V>,
+}
+
+#[derive(Debug)]
+struct Trace<V> {
+    steps : Vec<Step<V>>,
+}
+
+impl<V: Vector> Trace<V> {
+    fn new(ship: V, waypoint: Option<V>) -> Self {
+        Self { steps: vec![Step { action: None, ship, waypoint }] }
+    }
+
+    fn push(&mut self, action: Action, ship: V, waypoint: Option<V>) {
+        self.steps.push(Step { action: Some(action), ship, waypoint });
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("step,action,ship_x,ship_y,waypoint_x,waypoint_y\n");
+        for (i, step) in self.steps.iter().enumerate() {
+            let action = step.action.map_or(String::new(), |a| a.to_string());
+            let (x, y) = step.ship.coords();
+            let waypoint = step.waypoint.map_or(String::from(","), |w| {
+                let (wx, wy) = w.coords();
+                format!("{},{}", wx, wy)
+            });
+            csv.push_str(&format!("{},{},{},{},{}\n", i, action, x, y, waypoint));
+        }
+        csv
+    }
+
+    // north is up, so y is flipped going into svg coordinates
+    fn to_svg(&self) -> String {
+        let ship : Vec<(f64, f64)> = self.steps.iter().map(|s| s.ship.to_f64()).collect();
+        let arrows : Vec<((f64, f64), (f64, f64))> =
+            self.steps.iter()
+                      .zip(ship.iter())
+                      .filter_map(|(s, &(x, y))| s.waypoint.map(|w| {
+                          let (wx, wy) = w.to_f64();
+                          ((x, y), (x + wx, y + wy))
+                      }))
+                      .collect();
+
+        let points = ship.iter().chain(arrows.iter().map(|(_, tip)| tip));
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+        for &(x, y) in points {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        let margin = ((max_x - min_x).max(max_y - min_y) * 0.05).max(1.0);
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"800\" height=\"800\">\n",
+            min_x - margin, -max_y - margin, max_x - min_x + 2.0 * margin, max_y - min_y + 2.0 * margin);
+        for &((x, y), (tx, ty)) in arrows.iter() {
+            svg.push_str(&format!(
+                "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"orange\" stroke-width=\"1\" vector-effect=\"non-scaling-stroke\"/>\n",
+                x, -y, tx, -ty));
+        }
+        let path : Vec<String> = ship.iter().map(|&(x, y)| format!("{},{}", x, -y)).collect();
+        svg.push_str(&format!(
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\"/>\n",
+            path.join(" ")));
+        for &((x, y), colour) in [(ship[0], "green"), (ship[ship.len() - 1], "red")].iter() {
+            svg.push_str(&format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>\n", x, -y, margin / 4.0, colour));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Metric {
+    Manhattan,
+    Euclidean,
+    Chebyshev,
+}
+
+impl Metric {
+    fn from_name(name: &str) -> Option<Metric> {
+        match name {
+            "manhattan" => Some(Metric::Manhattan),
+            "euclidean" => Some(Metric::Euclidean),
+            "chebyshev" => Some(Metric::Chebyshev),
+            _ => None,
+        }
+    }
+}
+
+// a movement model, everything but how a single action moves things
+// around is shared
+trait Navigator: Sized {
+    type V: Vector;
+
+    fn new() -> Self;
+    fn take_action(&mut self, action: Action) -> Result<(), NavError>;
+    fn position(&self) -> Self::V;
+
+    fn waypoint(&self) -> Option<Self::V> {
+        None
+    }
+
+    fn from_instructions(instructions: &[Action]) -> Result<Self, NavError> {
         let mut state = Self::new();
         for &action in instructions {
-            //println!("state: {:?}", state);
-            //println!("next action: {:?}", action);
-            state.take_action(action);
+            state.take_action(action)?;
         }
-        //println!("final state: {:?}", state);
-        state
+        Ok(state)
     }
 
-    fn distance(&self) -> f64 {
-        self.x.abs() + self.y.abs()
+    fn trace(instructions: &[Action]) -> Result<Trace<Self::V>, NavError> {
+        let mut state = Self::new();
+        let mut trace = Trace::new(state.position(), state.waypoint());
+        for &action in instructions {
+            state.take_action(action)?;
+            trace.push(action, state.position(), state.waypoint());
+        }
+        Ok(trace)
+    }
+
+    // manhattan distance from the start, exact for exact vectors
+    fn distance(&self) -> <Self::V as Vector>::Scalar {
+        self.position().manhattan()
+    }
+
+    fn distance_by(&self, metric: Metric) -> f64 {
+        let (x, y) = self.position().to_f64();
+        match metric {
+            Metric::Manhattan => x.abs() + y.abs(),
+            Metric::Euclidean => x.hypot(y),
+            Metric::Chebyshev => x.abs().max(y.abs()),
+        }
     }
 }
 
 #[derive(Debug)]
-struct StatePart2 {
-    way_x : f64,
-    way_y : f64,
-    ship_x : f64,
-    ship_y : f64,
+struct StatePart1<V> {
+    ship : V,
+    heading : V,
+}
+
+impl<V: Vector> StatePart1<V> {
+    fn facing(heading: V) -> Self {
+        Self { ship: V::new(0, 0), heading }
+    }
 }
 
-impl StatePart2 {
+impl<V: Vector> Navigator for StatePart1<V> {
+    type V = V;
+
     fn new() -> Self {
-        Self { way_x: 10.0, way_y: 1.0, ship_x: 0.0, ship_y: 0.0 }
+        Self::facing(V::new(1, 0))
     }
 
-    fn take_action(&mut self, action : Action) {
+    fn take_action(&mut self, action : Action) -> Result<(), NavError> {
         match action {
-            Action::North(v) => self.way_y += v as f64,
-            Action::South(v) => self.way_y -= v as f64,
-            Action::East(v) => self.way_x += v as f64,
-            Action::West(v) => self.way_x -= v as f64,
-            Action::Left(a) => {
-                let (sin, cos) = (a as f64).to_radians().sin_cos();
-                let new_way_x = self.way_x * cos - self.way_y * sin;
-                let new_way_y = self.way_x * sin + self.way_y * cos;
-                self.way_x = new_way_x;
-                self.way_y = new_way_y;
-            },
-            Action::Right(a) => {
-                let (sin, cos) = (-a as f64).to_radians().sin_cos();
-                let new_way_x = self.way_x * cos - self.way_y * sin;
-                let new_way_y = self.way_x * sin + self.way_y * cos;
-                self.way_x = new_way_x;
-                self.way_y = new_way_y;
-            },
-            Action::Forward(v) => {
-                self.ship_x += (v as f64) * self.way_x;
-                self.ship_y += (v as f64) * self.way_y;
-            },
+            Action::North(v) => self.ship = self.ship.add(V::new(0, v)),
+            Action::South(v) => self.ship = self.ship.add(V::new(0, -v)),
+            Action::East(v) => self.ship = self.ship.add(V::new(v, 0)),
+            Action::West(v) => self.ship = self.ship.add(V::new(-v, 0)),
+            Action::Left(a) => self.heading = self.heading.rotate(a)?,
+            Action::Right(a) => self.heading = self.heading.rotate(-a)?,
+            Action::Forward(v) => self.ship = self.ship.add(self.heading.scale(v)),
         };
+        Ok(())
     }
 
-    fn from_instructions(instructions: &[Action]) -> Self {
-        let mut state = Self::new();
-        for &action in instructions {
-            //println!("state: {:?}", state);
-            //println!("next action: {:?}", action);
-            state.take_action(action);
+    fn position(&self) -> V {
+        self.ship
+    }
+}
+
+#[derive(Debug)]
+struct StatePart2<V> {
+    way : V,
+    ship : V,
+}
+
+impl<V: Vector> StatePart2<V> {
+    fn with_waypoint(way: V) -> Self {
+        Self { way, ship: V::new(0, 0) }
+    }
+}
+
+impl<V: Vector> Navigator for StatePart2<V> {
+    type V = V;
+
+    fn new() -> Self {
+        Self::with_waypoint(V::new(10, 1))
+    }
+
+    fn take_action(&mut self, action : Action) -> Result<(), NavError> {
+        match action {
+            Action::North(v) => self.way = self.way.add(V::new(0, v)),
+            Action::South(v) => self.way = self.way.add(V::new(0, -v)),
+            Action::East(v) => self.way = self.way.add(V::new(v, 0)),
+            Action::West(v) => self.way = self.way.add(V::new(-v, 0)),
+            Action::Left(a) => self.way = self.way.rotate(a)?,
+            Action::Right(a) => self.way = self.way.rotate(-a)?,
+            Action::Forward(v) => self.ship = self.ship.add(self.way.scale(v)),
+        };
+        Ok(())
+    }
+
+    fn position(&self) -> V {
+        self.ship
+    }
+
+    fn waypoint(&self) -> Option<V> {
+        Some(self.way)
+    }
+}
+
+// like part 2, but N/S/E/W carry the ship and the waypoint along together,
+// so the waypoint only changes by turning
+#[derive(Debug)]
+struct StateTandem<V> {
+    way : V,
+    ship : V,
+}
+
+impl<V: Vector> Navigator for StateTandem<V> {
+    type V = V;
+
+    fn new() -> Self {
+        Self { way: V::new(10, 1), ship: V::new(0, 0) }
+    }
+
+    fn take_action(&mut self, action : Action) -> Result<(), NavError> {
+        match action {
+            Action::North(v) => self.ship = self.ship.add(V::new(0, v)),
+            Action::South(v) => self.ship = self.ship.add(V::new(0, -v)),
+            Action::East(v) => self.ship = self.ship.add(V::new(v, 0)),
+            Action::West(v) => self.ship = self.ship.add(V::new(-v, 0)),
+            Action::Left(a) => self.way = self.way.rotate(a)?,
+            Action::Right(a) => self.way = self.way.rotate(-a)?,
+            Action::Forward(v) => self.ship = self.ship.add(self.way.scale(v)),
+        };
+        Ok(())
+    }
+
+    fn position(&self) -> V {
+        self.ship
+    }
+
+    fn waypoint(&self) -> Option<V> {
+        Some(self.way)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Model {
+    Ship,
+    Waypoint,
+}
+
+fn magnitude(v: i64) -> Result<i32, NavError> {
+    if v.unsigned_abs() <= i32::MAX as u64 {
+        Ok(v.unsigned_abs() as i32)
+    } else {
+        Err(NavError::TooFar(v))
+    }
+}
+
+// the single N/S/E/W action moving by delta along one axis
+fn compass(delta: i64, horizontal: bool) -> Result<Option<Action>, NavError> {
+    let v = magnitude(delta)?;
+    Ok(match (delta.signum(), horizontal) {
+        (0, _) => None,
+        (1, true) => Some(Action::East(v)),
+        (_, true) => Some(Action::West(v)),
+        (1, false) => Some(Action::North(v)),
+        (_, false) => Some(Action::South(v)),
+    })
+}
+
+// the k >= 1 with k * way == target, if there is one
+fn multiple_of(target: Exact, way: Exact) -> Option<i64> {
+    let k = if way.x != 0 {
+        target.x / way.x
+    } else if way.y != 0 {
+        target.y / way.y
+    } else {
+        return None;
+    };
+    if k >= 1 && way.scale(k as i32) == target { Some(k) } else { None }
+}
+
+fn divisors(n: i64) -> Vec<i64> {
+    let mut divisors = vec![];
+    let mut k = 1;
+    while k * k <= n {
+        if n % k == 0 {
+            divisors.push(k);
+            divisors.push(n / k);
+        }
+        k += 1;
+    }
+    divisors
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+// start is the ship's heading for Model::Ship and the waypoint for
+// Model::Waypoint
+fn plan_route(model: Model, start: Exact, target: Exact) -> Result<Vec<Action>, NavError> {
+    match model {
+        // one action per axis, going forward wherever the ship already faces
+        Model::Ship => {
+            let mut plan = vec![];
+            for &(delta, horizontal) in [(target.x, true), (target.y, false)].iter() {
+                let unit = if horizontal { Exact::new(delta.signum() as i32, 0) } else { Exact::new(0, delta.signum() as i32) };
+                match compass(delta, horizontal)? {
+                    Some(_) if unit == start => plan.push(Action::Forward(magnitude(delta)?)),
+                    Some(action) => plan.push(action),
+                    None => (),
+                }
+            }
+            Ok(plan)
+        },
+        // only F moves the ship, so the plan is at most two waypoint changes
+        // and then one F, with shorter plans tried first
+        Model::Waypoint => {
+            if target == Exact::new(0, 0) {
+                return Ok(vec![]);
+            }
+            if let Some(k) = multiple_of(target, start) {
+                return Ok(vec![Action::Forward(magnitude(k)?)]);
+            }
+            for &turn in [Action::Left(90), Action::Left(180), Action::Right(90)].iter() {
+                let degrees = match turn { Action::Right(a) => -a, Action::Left(a) => a, _ => 0 };
+                if let Some(k) = multiple_of(target, start.rotate(degrees)?) {
+                    return Ok(vec![turn, Action::Forward(magnitude(k)?)]);
+                }
+            }
+            for k in divisors(gcd(target.x, target.y)) {
+                let way = Exact { x: target.x / k, y: target.y / k };
+                let adjust = if way.y == start.y {
+                    compass(way.x - start.x, true)?
+                } else if way.x == start.x {
+                    compass(way.y - start.y, false)?
+                } else {
+                    continue;
+                };
+                return Ok(adjust.into_iter().chain(Some(Action::Forward(magnitude(k)?))).collect());
+            }
+            let mut plan = vec![];
+            plan.extend(compass(target.x - start.x, true)?);
+            plan.extend(compass(target.y - start.y, false)?);
+            plan.push(Action::Forward(1));
+            Ok(plan)
+        },
+    }
+}
+
+fn replay(model: Model, start: Exact, actions: &[Action]) -> Result<Exact, NavError> {
+    fn run<N: Navigator<V = Exact>>(mut ship: N, actions: &[Action]) -> Result<Exact, NavError> {
+        for &action in actions {
+            ship.take_action(action)?;
         }
-        //println!("final state: {:?}", state);
-        state
+        Ok(ship.position())
+    }
+    match model {
+        Model::Ship => run(StatePart1::facing(start), actions),
+        Model::Waypoint => run(StatePart2::with_waypoint(start), actions),
     }
+}
 
-    fn distance(&self) -> f64 {
-        self.ship_x.abs() + self.ship_y.abs()
+fn report<N: Navigator>(name: &str, actions: &[Action], metric: Metric) {
+    match N::from_instructions(actions) {
+        Ok(state) => println!("{} {:?} distance {}", name, metric, state.distance_by(metric)),
+        Err(e) => println!("{}: {}", name, e),
     }
 }
 
@@ -141,13 +560,54 @@ fn main() {
     R90
     F11";
 
-    println!("test result {}", StatePart1::from_instructions(&Action::from_instructions(&test_input).expect("valid instructions")).distance());
+    let test_actions = Action::from_instructions(test_input).expect("valid instructions");
+    println!("test result {}", StatePart1::<Exact>::from_instructions(&test_actions).expect("quarter turns").distance());
 
     let real_input = include_str!("day12.txt");
-    println!("real result {}", StatePart1::from_instructions(&Action::from_instructions(&real_input).expect("valid instructions")).distance());
+    let real_actions = Action::from_instructions(real_input).expect("valid instructions");
+    println!("real result {}", StatePart1::<Exact>::from_instructions(&real_actions).expect("quarter turns").distance());
 
-    println!("test round two {}", StatePart2::from_instructions(&Action::from_instructions(&test_input).expect("valid instructions")).distance());
-    println!("real round two {}", StatePart2::from_instructions(&Action::from_instructions(&real_input).expect("valid instructions")).distance());
+    println!("test round two {}", StatePart2::<Exact>::from_instructions(&test_actions).expect("quarter turns").distance());
+    println!("real round two {}", StatePart2::<Exact>::from_instructions(&real_actions).expect("quarter turns").distance());
+
+    let odd_actions = Action::from_instructions("F10\nL45\nF10").expect("valid instructions");
+    if let Err(e) = StatePart1::<Exact>::from_instructions(&odd_actions) {
+        println!("{}", e);
+    }
+    println!("floating result for a 45 degree turn {}", StatePart1::<Approx>::from_instructions(&odd_actions).expect("any angle").distance());
+    println!("floating real round two {}", StatePart2::<Approx>::from_instructions(&real_actions).expect("any angle").distance());
+    println!("test tandem {}", StateTandem::<Exact>::from_instructions(&test_actions).expect("quarter turns").distance());
+    for &metric in [Metric::Manhattan, Metric::Euclidean, Metric::Chebyshev].iter() {
+        report::<StatePart2<Exact>>("test round two", &test_actions, metric);
+    }
 
+    print!("{}", StatePart2::<Exact>::trace(&test_actions).expect("quarter turns").to_csv());
+    let targets = [Exact::new(214, -72), Exact::new(17, 8), Exact::new(-30, -3), Exact::new(0, 0), Exact::new(7, 0), Exact::new(-3, 12)];
+    for &model in [Model::Ship, Model::Waypoint].iter() {
+        for &target in targets.iter() {
+            let start = if model == Model::Ship { Exact::new(1, 0) } else { Exact::new(10, 1) };
+            let plan = plan_route(model, start, target).expect("reachable");
+            assert_eq!(replay(model, start, &plan), Ok(target));
+            let plan : Vec<String> = plan.iter().map(|a| a.to_string()).collect();
+            println!("{:?} plan to {:?}: {}", model, target.coords(), plan.join(" "));
+        }
+    }
 
+    let args : Vec<String> = std::env::args().collect();
+    let metric = args.get(2).and_then(|m| Metric::from_name(m)).unwrap_or(Metric::Manhattan);
+    match args.get(1).map(|a| a.as_str()) {
+        Some("ship") => report::<StatePart1<Exact>>("ship", &real_actions, metric),
+        Some("waypoint") => report::<StatePart2<Exact>>("waypoint", &real_actions, metric),
+        Some("tandem") => report::<StateTandem<Exact>>("tandem", &real_actions, metric),
+        _ => (),
+    }
+    if args.get(1).map(|a| a.as_str()) == Some("trace") {
+        let part1 = StatePart1::<Exact>::trace(&real_actions).expect("quarter turns");
+        let part2 = StatePart2::<Exact>::trace(&real_actions).expect("quarter turns");
+        std::fs::write("day12_part1.csv", part1.to_csv()).expect("write csv");
+        std::fs::write("day12_part1.svg", part1.to_svg()).expect("write svg");
+        std::fs::write("day12_part2.csv", part2.to_csv()).expect("write csv");
+        std::fs::write("day12_part2.svg", part2.to_svg()).expect("write svg");
+        println!("wrote day12_part1 and day12_part2 csv and svg traces");
+    }
 }
\ No newline at end of file
@@ -1,7 +1,9 @@
 use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, Default)]
 struct Bag {
+    label : String,
     incoming : Vec<(usize, usize)>,
     outgoing : Vec<(usize, usize)>,
 }
@@ -18,7 +20,7 @@ impl Bags {
             *self.bags_map.get(label).unwrap()
         } else {
             let idx = self.bags.len();
-            self.bags.push(Bag::default());
+            self.bags.push(Bag { label: String::from(label), ..Bag::default() });
             self.bags_map.insert(String::from(label), idx);
             idx
         }
@@ -31,6 +33,93 @@ impl Bags {
         self.bags[outer_idx].outgoing.push((inner_idx, count));
         self.bags[inner_idx].incoming.push((outer_idx, count));
     }
+
+    fn find_cycle(&self) -> Option<Vec<usize>> {
+        #[derive(Copy, Clone, PartialEq)]
+        enum Mark {
+            Unseen,
+            OnPath,
+            Done,
+        }
+
+        let mut marks = vec![Mark::Unseen; self.bags.len()];
+        for start in 0..self.bags.len() {
+            if marks[start] != Mark::Unseen {
+                continue;
+            }
+            // explicit stack of (bag, next outgoing edge to try)
+            let mut path : Vec<(usize, usize)> = vec![(start, 0)];
+            marks[start] = Mark::OnPath;
+            while let Some(&mut (idx, ref mut edge)) = path.last_mut() {
+                if let Some(&(next, _count)) = self.bags[idx].outgoing.get(*edge) {
+                    *edge += 1;
+                    match marks[next] {
+                        Mark::Unseen => {
+                            marks[next] = Mark::OnPath;
+                            path.push((next, 0));
+                        },
+                        Mark::OnPath => {
+                            let pos = path.iter().position(|&(i, _)| i == next).unwrap();
+                            let mut cycle : Vec<usize> =
+                                path[pos..].iter().map(|&(i, _)| i).collect();
+                            cycle.push(next);
+                            return Some(cycle);
+                        },
+                        Mark::Done => (),
+                    }
+                } else {
+                    marks[idx] = Mark::Done;
+                    path.pop();
+                }
+            }
+        }
+        None
+    }
+
+    fn cycle_error(&self) -> BagsError {
+        let cycle = self.find_cycle().expect("graph has a cycle");
+        BagsError::Cycle(cycle.iter().map(|&i| self.bags[i].label.clone()).collect())
+    }
+
+    // outermost bags first, every bag before the bags it contains
+    fn topological_order(&self) -> Result<Vec<usize>, BagsError> {
+        let mut remaining : Vec<usize> =
+            self.bags.iter().map(|b| b.incoming.len()).collect();
+        let mut ready : Vec<usize> =
+            (0..self.bags.len()).filter(|&i| remaining[i] == 0).collect();
+        let mut order = Vec::with_capacity(self.bags.len());
+        while let Some(idx) = ready.pop() {
+            order.push(idx);
+            for &(inner, _count) in self.bags[idx].outgoing.iter() {
+                remaining[inner] -= 1;
+                if remaining[inner] == 0 {
+                    ready.push(inner);
+                }
+            }
+        }
+        if order.len() == self.bags.len() {
+            Ok(order)
+        } else {
+            Err(self.cycle_error())
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BagsError {
+    Cycle(Vec<String>),
+    UnknownBag(String),
+    Overflow(String),
+}
+
+impl fmt::Display for BagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BagsError::Cycle(path) => write!(f, "bag rules are cyclic: {}", path.join(" -> ")),
+            BagsError::UnknownBag(label) => write!(f, "no rule mentions {} bags", label),
+            BagsError::Overflow(label) => write!(f, "bags inside {} overflow a usize", label),
+        }
+    }
 }
 
 fn process_line(line: &str, bags: &mut Bags) {
@@ -62,47 +151,269 @@ fn process_line(line: &str, bags: &mut Bags) {
     }
 }
 
-fn find_containable_recursive(
-    idx: usize,
-    bags: &mut Bags,
-    acc: Vec<usize>,
-) -> Vec<usize> {
-    let mut containing_bags : Vec<usize> =
-        bags.bags[idx].incoming.iter()
-                               .map(|(bag_idx, _c)| *bag_idx)
-                               .collect();
-    let mut acc = acc;
-    for &bag_idx in containing_bags.iter() {
-        acc = find_containable_recursive(bag_idx, bags, acc)
+// little-endian base 2^32 digits, no trailing zero digits
+#[derive(Debug, Clone, Default, PartialEq)]
+struct BigUint {
+    digits : Vec<u32>,
+}
+
+impl From<usize> for BigUint {
+    fn from(mut n: usize) -> BigUint {
+        let mut digits = vec![];
+        while n > 0 {
+            digits.push(n as u32);
+            n = ((n as u64) >> 32) as usize;
+        }
+        BigUint { digits }
     }
-    acc.append(&mut containing_bags);
-    acc
 }
 
-fn find_containable_count(label: &str, bags: &mut Bags) -> usize {
-    let bag_idx = bags.get_bag_idx(label);
-    let mut all_containers = find_containable_recursive(bag_idx, bags, Vec::new());
-    all_containers.sort();
-    all_containers.dedup();
-    all_containers.len()
+impl BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        let mut carry = 0u64;
+        for i in 0..self.digits.len().max(other.digits.len()) {
+            if i == self.digits.len() {
+                self.digits.push(0);
+            }
+            let sum = self.digits[i] as u64 + *other.digits.get(i).unwrap_or(&0) as u64 + carry;
+            self.digits[i] = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+
+    fn mul(&self, other: &BigUint) -> BigUint {
+        if self.digits.is_empty() || other.digits.is_empty() {
+            return BigUint::default();
+        }
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let prod = a as u64 * b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = prod as u32;
+                carry = prod >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigUint { digits }
+    }
 }
 
-fn reduce_contained_recursive(
-    idx: usize,
-    bags: &mut Bags,
-    acc : usize
-) -> usize {
-    let contained_bags = bags.bags[idx].outgoing.clone();
-    let mut acc = acc;
-    for (bag_idx, num) in contained_bags {
-        acc += num * reduce_contained_recursive(bag_idx, bags, 0);
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // peel off base 10^9 chunks, least significant first
+        let mut digits = self.digits.clone();
+        let mut chunks = vec![];
+        while !digits.is_empty() {
+            let mut rem = 0u64;
+            for d in digits.iter_mut().rev() {
+                let cur = (rem << 32) | *d as u64;
+                *d = (cur / 1_000_000_000) as u32;
+                rem = cur % 1_000_000_000;
+            }
+            while digits.last() == Some(&0) {
+                digits.pop();
+            }
+            chunks.push(rem);
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                for chunk in rest.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            }
+        }
     }
-    acc + 1
 }
 
-fn reduce_contained(label: &str, bags: &mut Bags) -> usize {
-    let bag_idx = bags.get_bag_idx(label);
-    reduce_contained_recursive(bag_idx, bags, 0) - 1
+struct BagIndex<'a> {
+    bags : &'a Bags,
+    order : Vec<usize>,
+    inside : Vec<Option<usize>>,
+    containers : Vec<Vec<usize>>,
+}
+
+impl<'a> BagIndex<'a> {
+    fn new(bags: &'a Bags) -> Result<Self, BagsError> {
+        let order = bags.topological_order()?;
+        let n = bags.bags.len();
+
+        // every outer bag is finished before the bags it contains
+        let mut containers : Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut stamp = vec![usize::MAX; n];
+        for &idx in order.iter() {
+            let mut all = Vec::new();
+            for &(outer, _count) in bags.bags[idx].incoming.iter() {
+                for &c in std::iter::once(&outer).chain(containers[outer].iter()) {
+                    if stamp[c] != idx {
+                        stamp[c] = idx;
+                        all.push(c);
+                    }
+                }
+            }
+            containers[idx] = all;
+        }
+
+        // and every inner bag is finished before the bags containing it
+        // None once a subtotal no longer fits in a usize
+        let mut inside : Vec<Option<usize>> = vec![Some(0); n];
+        for &idx in order.iter().rev() {
+            inside[idx] = bags.bags[idx].outgoing.iter()
+                                                 .try_fold(0usize, |acc, &(inner, count)| {
+                                                     let sub = inside[inner]?.checked_add(1)?;
+                                                     acc.checked_add(count.checked_mul(sub)?)
+                                                 });
+        }
+
+        Ok(Self { bags, order, inside, containers })
+    }
+
+    fn idx(&self, label: &str) -> Result<usize, BagsError> {
+        self.bags.bags_map
+                 .get(label)
+                 .copied()
+                 .ok_or_else(|| BagsError::UnknownBag(String::from(label)))
+    }
+
+    fn containers_of(&self, label: &str) -> Result<Vec<&'a str>, BagsError> {
+        let bags = self.bags;
+        Ok(self.containers[self.idx(label)?].iter()
+                                            .map(|&i| bags.bags[i].label.as_str())
+                                            .collect())
+    }
+
+    fn total_inside(&self, label: &str) -> Result<usize, BagsError> {
+        self.inside[self.idx(label)?].ok_or_else(|| BagsError::Overflow(String::from(label)))
+    }
+
+    fn total_inside_exact(&self, label: &str) -> Result<BigUint, BagsError> {
+        let target = self.idx(label)?;
+        let mut inside = vec![BigUint::default(); self.bags.bags.len()];
+        for &idx in self.order.iter().rev() {
+            let mut total = BigUint::default();
+            for &(inner, count) in self.bags.bags[idx].outgoing.iter() {
+                let mut sub = inside[inner].clone();
+                sub.add_assign(&BigUint::from(1));
+                total.add_assign(&sub.mul(&BigUint::from(count)));
+            }
+            inside[idx] = total;
+        }
+        Ok(inside.swap_remove(target))
+    }
+
+    // shortest chain of bags from outer down to inner, both included
+    fn path_between(&self, outer: &str, inner: &str) -> Result<Option<Vec<&'a str>>, BagsError> {
+        let from = self.idx(outer)?;
+        let to = self.idx(inner)?;
+        let mut parent = vec![None; self.bags.bags.len()];
+        let mut queue = std::collections::VecDeque::new();
+        parent[from] = Some(from);
+        queue.push_back(from);
+        while let Some(idx) = queue.pop_front() {
+            if idx == to {
+                let mut path = vec![self.bags.bags[to].label.as_str()];
+                let mut curr = to;
+                while curr != from {
+                    curr = parent[curr].unwrap();
+                    path.push(self.bags.bags[curr].label.as_str());
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for &(next, _count) in self.bags.bags[idx].outgoing.iter() {
+                if parent[next].is_none() {
+                    parent[next] = Some(idx);
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum DotScope {
+    All,
+    Inside,
+    Containers,
+    Connected,
+}
+
+impl<'a> BagIndex<'a> {
+    fn contained_set(&self, idx: usize) -> Vec<bool> {
+        let mut seen = vec![false; self.bags.bags.len()];
+        let mut stack = vec![idx];
+        while let Some(curr) = stack.pop() {
+            for &(inner, _count) in self.bags.bags[curr].outgoing.iter() {
+                if !seen[inner] {
+                    seen[inner] = true;
+                    stack.push(inner);
+                }
+            }
+        }
+        seen
+    }
+
+    fn to_dot(&self, focus: &str, scope: DotScope) -> Result<String, BagsError> {
+        let focus_idx = self.idx(focus)?;
+        let inside = self.contained_set(focus_idx);
+        let mut containers = vec![false; self.bags.bags.len()];
+        for &c in self.containers[focus_idx].iter() {
+            containers[c] = true;
+        }
+
+        let shown : Vec<bool> = (0..self.bags.bags.len())
+            .map(|i| i == focus_idx || match scope {
+                DotScope::All => true,
+                DotScope::Inside => inside[i],
+                DotScope::Containers => containers[i],
+                DotScope::Connected => inside[i] || containers[i],
+            })
+            .collect();
+
+        let mut dot = String::from("digraph bags {\n    node [style=filled, fillcolor=white];\n");
+        for (i, bag) in self.bags.bags.iter().enumerate() {
+            if !shown[i] {
+                continue;
+            }
+            let attrs = if i == focus_idx {
+                String::from(", fillcolor=gold, penwidth=2")
+            } else if containers[i] {
+                String::from(", fillcolor=lightblue")
+            } else if inside[i] {
+                String::from(", fillcolor=lightsalmon")
+            } else {
+                String::new()
+            };
+            dot.push_str(&format!("    \"{}\" [label=\"{}\"{}];\n", i, bag.label, attrs));
+        }
+        for (i, bag) in self.bags.bags.iter().enumerate() {
+            for &(inner, count) in bag.outgoing.iter() {
+                if shown[i] && shown[inner] {
+                    dot.push_str(&format!("    \"{}\" -> \"{}\" [label=\"{}\"];\n", i, inner, count));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+}
+
+fn find_containable_count(label: &str, bags: &Bags) -> Result<usize, BagsError> {
+    Ok(BagIndex::new(bags)?.containers_of(label)?.len())
+}
+
+fn reduce_contained(label: &str, bags: &Bags) -> Result<usize, BagsError> {
+    BagIndex::new(bags)?.total_inside(label)
 }
 
 fn main() {
@@ -121,7 +432,14 @@ fn main() {
         process_line(line, &mut bags);
     }
 
-    println!("count is {}", find_containable_count("shiny gold", &mut bags));
+    println!("count is {}", find_containable_count("shiny gold", &bags).expect("acyclic rules"));
+    let order : Vec<&str> =
+        bags.topological_order()
+            .expect("acyclic rules")
+            .iter()
+            .map(|&i| bags.bags[i].label.as_str())
+            .collect();
+    println!("topological order is {:?}", order);
 
     let test_input = "\
     shiny gold bags contain 2 dark red bags.
@@ -136,13 +454,58 @@ fn main() {
     for line in test_input.lines() {
         process_line(line, &mut bags);
     }
-    println!("test output sum count is {}", reduce_contained("shiny gold", &mut bags));
+    println!("test output sum count is {}", reduce_contained("shiny gold", &bags).expect("acyclic rules"));
+
+    let test_input = "\
+    shiny gold bags contain 1 dark red bag.
+    dark red bags contain 2 dark orange bags, 1 faded blue bag.
+    dark orange bags contain 1 shiny gold bag.
+    faded blue bags contain no other bags.";
+
+    let mut bags = Bags::default();
+    for line in test_input.lines() {
+        process_line(line, &mut bags);
+    }
+    match reduce_contained("shiny gold", &bags) {
+        Ok(count) => println!("cyclic sum count is {}", count),
+        Err(e) => println!("{}", e),
+    }
 
     let real_input = include_str!("day7.txt");
     let mut bags = Bags::default();
     for line in real_input.lines() {
         process_line(line, &mut bags);
     }
-    println!("real count is {}", find_containable_count("shiny gold", &mut bags));
-    println!("real sum count is {}", reduce_contained("shiny gold", &mut bags));
+    println!("real count is {}", find_containable_count("shiny gold", &bags).expect("acyclic rules"));
+    println!("real sum count is {}", reduce_contained("shiny gold", &bags).expect("acyclic rules"));
+
+    let index = BagIndex::new(&bags).expect("acyclic rules");
+    println!("real containers are {:?}", index.containers_of("shiny gold").expect("known bag"));
+    println!("real total inside is {}", index.total_inside("shiny gold").expect("known bag"));
+    if let Some(path) = index.path_between("light red", "shiny gold").expect("known bags") {
+        println!("light red reaches shiny gold via {}", path.join(" -> "));
+    }
+
+    // the dark red -> dark violet doubling chain, deep enough to overflow a u64
+    let mut bags = Bags::default();
+    let shades : Vec<String> = (0..80).map(|i| format!("dark shade{}", i)).collect();
+    for pair in shades.windows(2) {
+        bags.add_to_bag(&pair[0], &pair[1], 2);
+    }
+    let deep_index = BagIndex::new(&bags).expect("acyclic rules");
+    match deep_index.total_inside("dark shade0") {
+        Ok(count) => println!("deep sum count is {}", count),
+        Err(e) => println!("{}", e),
+    }
+    println!("exact deep sum count is {}", deep_index.total_inside_exact("dark shade0").expect("known bag"));
+
+    let scope = match std::env::args().nth(1).as_deref() {
+        Some("all") => DotScope::All,
+        Some("inside") => DotScope::Inside,
+        Some("containers") => DotScope::Containers,
+        _ => DotScope::Connected,
+    };
+    let dot = index.to_dot("shiny gold", scope).expect("known bag");
+    std::fs::write("day7.dot", dot).expect("write day7.dot");
+    println!("wrote shiny gold graph to day7.dot");
 }
\ No newline at end of file
@@ -1,102 +1,654 @@
-#[derive(Debug, Copy, Clone)]
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Operation {
     Acc(i32),
     Jmp(i32),
     Nop(i32),
 }
 
-impl From<&str> for Operation {
-    fn from(line: &str) -> Operation {
-        let line = line.trim();
-        let immediate = line[4..].parse().unwrap();
-        match &line[0..3] {
-            "acc" => Operation::Acc(immediate),
-            "jmp" => Operation::Jmp(immediate),
-            "nop" => Operation::Nop(immediate),
-            _ => panic!("line not valid"),
+impl Operation {
+    // all instruction semantics live here and in successor
+    fn execute(self, pc: usize, acc: &mut i32) -> i64 {
+        if let Operation::Acc(val) = self {
+            *acc += val;
+        }
+        self.successor(pc)
+    }
+
+    fn successor(self, pc: usize) -> i64 {
+        match self {
+            Operation::Nop(_) | Operation::Acc(_) => pc as i64 + 1,
+            Operation::Jmp(val) => pc as i64 + val as i64,
+        }
+    }
+
+    fn flipped(self) -> Option<Operation> {
+        match self {
+            Operation::Acc(_) => None,
+            Operation::Jmp(x) => Some(Operation::Nop(x)),
+            Operation::Nop(x) => Some(Operation::Jmp(x)),
         }
     }
 }
 
-fn parse_program(program: &str) -> Vec<Operation> {
-    program.lines()
-           .map(|line| Operation::from(line))
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Acc(x) => write!(f, "acc {:+}", x),
+            Operation::Jmp(x) => write!(f, "jmp {:+}", x),
+            Operation::Nop(x) => write!(f, "nop {:+}", x),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AsmErrorKind {
+    UnknownMnemonic(String),
+    MissingOperand,
+    ExtraOperand(String),
+    BadImmediate(String),
+    BadLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct AsmError {
+    line : usize,
+    kind : AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic {:?}", m),
+            AsmErrorKind::MissingOperand => write!(f, "missing operand"),
+            AsmErrorKind::ExtraOperand(o) => write!(f, "unexpected operand {:?}", o),
+            AsmErrorKind::BadImmediate(o) => write!(f, "{:?} is not a number or label", o),
+            AsmErrorKind::BadLabel(l) => write!(f, "{:?} is not a valid label", l),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "label {:?} defined twice", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "label {:?} is never defined", l),
+        }
+    }
+}
+
+enum Operand<'s> {
+    Immediate(i32),
+    Label(&'s str),
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// one instruction per line, "#" or ";" start a comment, "name:" defines a
+// label and jmp/nop may take a label instead of a relative offset
+fn assemble(source: &str) -> Result<Vec<Operation>, AsmError> {
+    let mut labels : HashMap<&str, usize> = HashMap::new();
+    let mut pending = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let err = |kind| AsmError { line: i + 1, kind };
+        let mut line = line.split(['#', ';']).next().unwrap().trim();
+        while let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(err(AsmErrorKind::BadLabel(String::from(label))));
+            }
+            if labels.insert(label, pending.len()).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(String::from(label))));
+            }
+            line = rest.trim();
+        }
+
+        let mut words = line.split_whitespace();
+        let mnemonic = match words.next() {
+            Some(m) => m.to_ascii_lowercase(),
+            None => continue,
+        };
+        let operand = words.next().ok_or_else(|| err(AsmErrorKind::MissingOperand))?;
+        if let Some(extra) = words.next() {
+            return Err(err(AsmErrorKind::ExtraOperand(String::from(extra))));
+        }
+        let operand = match operand.parse::<i32>() {
+            Ok(x) => Operand::Immediate(x),
+            Err(_) if mnemonic != "acc" && is_label(operand) => Operand::Label(operand),
+            Err(_) => return Err(err(AsmErrorKind::BadImmediate(String::from(operand)))),
+        };
+        let build : fn(i32) -> Operation = match mnemonic.as_str() {
+            "acc" => Operation::Acc,
+            "jmp" => Operation::Jmp,
+            "nop" => Operation::Nop,
+            _ => return Err(err(AsmErrorKind::UnknownMnemonic(mnemonic))),
+        };
+        pending.push((i + 1, build, operand));
+    }
+
+    pending.iter()
+           .enumerate()
+           .map(|(idx, &(line, build, ref operand))| match *operand {
+               Operand::Immediate(x) => Ok(build(x)),
+               Operand::Label(label) => labels.get(label)
+                                              .map(|&target| build(target as i32 - idx as i32))
+                                              .ok_or_else(|| AsmError {
+                                                  line,
+                                                  kind: AsmErrorKind::UndefinedLabel(String::from(label)),
+                                              }),
+           })
+           .collect()
+}
+
+fn disassemble(program: &[Operation]) -> String {
+    program.iter()
+           .map(|op| format!("{}\n", op))
            .collect()
 }
 
-struct MachineState {
+#[derive(Debug, Clone)]
+struct VisitedSet {
+    words : Vec<u64>,
+}
+
+impl VisitedSet {
+    fn new(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(64)] }
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        self.words.get(idx / 64).is_some_and(|w| w & (1 << (idx % 64)) != 0)
+    }
+
+    fn insert(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum HaltReason {
+    End,
+    PastEnd,
+    BelowZero,
+    LoopDetected,
+    StepLimit,
+}
+
+// running off the program always halts, the rest is up to the caller
+#[derive(Debug, Copy, Clone)]
+struct HaltConditions {
+    on_loop : bool,
+    step_limit : Option<usize>,
+}
+
+impl Default for HaltConditions {
+    fn default() -> Self {
+        Self { on_loop: true, step_limit: None }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Event {
+    Executed { pc: usize, op: Operation },
+    Halted(HaltReason),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct FinalState {
+    pc : i64,
+    acc : i32,
+    steps : usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Termination {
+    NormalEnd(FinalState),
+    JumpedPastEnd(FinalState),
+    JumpedBelowZero(FinalState),
+    InfiniteLoop(FinalState),
+    StepLimit(FinalState),
+}
+
+impl Termination {
+    fn state(&self) -> FinalState {
+        match *self {
+            Termination::NormalEnd(s)
+            | Termination::JumpedPastEnd(s)
+            | Termination::JumpedBelowZero(s)
+            | Termination::InfiniteLoop(s)
+            | Termination::StepLimit(s) => s,
+        }
+    }
+}
+
+struct MachineState<'a> {
+    acc : i32,
+    pc : i64,
+    steps : usize,
+    program : &'a [Operation],
+    visited : VisitedSet,
+    halt : HaltConditions,
+}
+
+impl<'a> MachineState<'a> {
+    fn new(program: &'a [Operation]) -> Self {
+        Self::with_halt(program, HaltConditions::default())
+    }
+
+    fn with_halt(program: &'a [Operation], halt: HaltConditions) -> Self {
+        MachineState {
+            acc: 0,
+            pc: 0,
+            steps: 0,
+            program,
+            visited: VisitedSet::new(program.len()),
+            halt,
+        }
+    }
+
+    fn step(&mut self) -> Event {
+        let pc = match self.current() {
+            Some(pc) => pc,
+            None if self.pc < 0 => return Event::Halted(HaltReason::BelowZero),
+            None if self.pc == self.program.len() as i64 => return Event::Halted(HaltReason::End),
+            None => return Event::Halted(HaltReason::PastEnd),
+        };
+        if self.halt.on_loop && self.visited.contains(pc) {
+            return Event::Halted(HaltReason::LoopDetected);
+        }
+        if self.halt.step_limit.is_some_and(|limit| self.steps >= limit) {
+            return Event::Halted(HaltReason::StepLimit);
+        }
+        let op = self.program[pc];
+        self.visited.insert(pc);
+        self.pc = op.execute(pc, &mut self.acc);
+        self.steps += 1;
+        Event::Executed { pc, op }
+    }
+
+    // the instruction about to run, if pc is inside the program
+    fn current(&self) -> Option<usize> {
+        if self.pc >= 0 && (self.pc as usize) < self.program.len() {
+            Some(self.pc as usize)
+        } else {
+            None
+        }
+    }
+
+    fn run(&mut self) -> HaltReason {
+        loop {
+            if let Event::Halted(reason) = self.step() {
+                break reason;
+            }
+        }
+    }
+
+    fn run_till_end(mut self) -> Termination {
+        let reason = self.run();
+        let state = FinalState { pc: self.pc, acc: self.acc, steps: self.steps };
+        match reason {
+            HaltReason::End => Termination::NormalEnd(state),
+            HaltReason::PastEnd => Termination::JumpedPastEnd(state),
+            HaltReason::BelowZero => Termination::JumpedBelowZero(state),
+            HaltReason::LoopDetected => Termination::InfiniteLoop(state),
+            HaltReason::StepLimit => Termination::StepLimit(state),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Repair {
+    index : usize,
+    replacement : Operation,
     acc : i32,
-    pc : usize,
-    program : Vec<Operation>,
-    visited : Vec<usize>,
 }
 
-impl MachineState {
-    fn new(program: Vec<Operation>) -> MachineState {
-        MachineState { acc: 0, pc: 0, program: program, visited: vec![] }
+// the flip has to be on the path the broken program actually runs, and it
+// has to send that path somewhere that already ends just past the last
+// instruction; a program that ends as it is needs no repair
+fn find_repair(program: &[Operation]) -> Option<Repair> {
+    let n = program.len();
+    let in_range = |pc: i64| pc >= 0 && (pc as usize) < n;
+
+    let mut preds = vec![vec![]; n];
+    let mut ends = vec![false; n];
+    let mut stack = vec![];
+    for (pc, op) in program.iter().enumerate() {
+        let next = op.successor(pc);
+        if in_range(next) {
+            preds[next as usize].push(pc);
+        } else if next == n as i64 {
+            ends[pc] = true;
+            stack.push(pc);
+        }
+    }
+    while let Some(pc) = stack.pop() {
+        for &p in preds[pc].iter() {
+            if !ends[p] {
+                ends[p] = true;
+                stack.push(p);
+            }
+        }
     }
 
-    fn run_step(mut self) -> Self {
-        let op = self.program[self.pc];
-        self.visited.push(self.pc);
-        match op {
-            Operation::Nop(_) => MachineState { 
-                pc: (self.pc + 1),
-                ..self
+    if n == 0 || ends[0] {
+        return None;
+    }
+
+    // a tail that came back through the run so far would reach the flip
+    // again and loop, so it is walked against the visited set
+    let mut visited = VisitedSet::new(n);
+    let mut pc = 0;
+    let mut acc = 0;
+    while pc < n && !visited.contains(pc) {
+        visited.insert(pc);
+        if let Some(replacement) = program[pc].flipped() {
+            let next = replacement.successor(pc);
+            if next == n as i64 || (in_range(next) && ends[next as usize]) {
+                let mut end_acc = acc;
+                let mut end_pc = next;
+                while in_range(end_pc) && !visited.contains(end_pc as usize) {
+                    let at = end_pc as usize;
+                    if let Operation::Acc(x) = program[at] {
+                        end_acc += x;
+                    }
+                    end_pc = program[at].successor(at);
+                }
+                if end_pc == n as i64 {
+                    return Some(Repair { index: pc, replacement, acc: end_acc });
+                }
+            }
+        }
+        let next = program[pc].execute(pc, &mut acc);
+        if !in_range(next) {
+            break;
+        }
+        pc = next as usize;
+    }
+    None
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Watch {
+    Changed,
+    Equals(i32),
+}
+
+struct Debugger<'a> {
+    machine : MachineState<'a>,
+    breakpoints : BTreeSet<usize>,
+    watch : Option<Watch>,
+    trace : bool,
+}
+
+impl<'a> Debugger<'a> {
+    fn new(machine: MachineState<'a>) -> Self {
+        Self { machine, breakpoints: BTreeSet::new(), watch: None, trace: false }
+    }
+
+    // false once the machine refuses to run any further
+    fn step<W: Write>(&mut self, out: &mut W, verbose: bool) -> io::Result<bool> {
+        let before = self.machine.acc;
+        match self.machine.step() {
+            Event::Executed { pc, op } => {
+                if verbose || self.trace {
+                    writeln!(out, "{:>5}: {:<10} acc {} -> {}", pc, op.to_string(), before, self.machine.acc)?;
+                }
+                Ok(true)
             },
-            Operation::Acc(val) => MachineState {
-                pc: (self.pc + 1),
-                acc: (self.acc + val),
-                ..self
+            Event::Halted(reason) => {
+                writeln!(out, "halted by {:?} at pc {}, acc {}", reason, self.machine.pc, self.machine.acc)?;
+                if reason == HaltReason::LoopDetected {
+                    if let Some(pc) = self.machine.current() {
+                        writeln!(out, "{:>5}: {} would run a second time", pc, self.machine.program[pc])?;
+                    }
+                }
+                Ok(false)
             },
-            Operation::Jmp(val) => MachineState {
-                pc: (self.pc as i64 + val as i64) as usize,
-                ..self
-            }
         }
     }
 
-    fn run_till_end(self) -> Result<i32, i32> {
-        let mut state = self;
+    fn cont<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
         loop {
-            if state.visited.contains(&state.pc) {
-                break Err(state.acc);
-            } else if state.pc >= state.program.len() {
-                break Ok(state.acc);
+            let before = self.machine.acc;
+            if !self.step(out, false)? {
+                return Ok(());
+            }
+            let acc = self.machine.acc;
+            let watched = match self.watch {
+                Some(Watch::Changed) => acc != before,
+                Some(Watch::Equals(x)) => acc == x && before != x,
+                None => false,
+            };
+            if watched {
+                return writeln!(out, "watch: acc {} -> {} at pc {}", before, acc, self.machine.pc);
+            }
+            if self.machine.current().is_some_and(|pc| self.breakpoints.contains(&pc)) {
+                return writeln!(out, "breakpoint at pc {}", self.machine.pc);
             }
-            state = state.run_step();
         }
     }
 
-    fn flip_instruction(&mut self, op : usize) {
-        let original_instruction = self.program[op];
-        self.program[op] = match original_instruction {
-            Operation::Acc(x) => Operation::Acc(x),
-            Operation::Jmp(x) => Operation::Nop(x),
-            Operation::Nop(x) => Operation::Jmp(x),
-        };
+    fn list<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let program = self.machine.program;
+        let pc = self.machine.pc.clamp(0, program.len() as i64) as usize;
+        let start = pc.saturating_sub(3);
+        for (i, op) in program.iter().enumerate().skip(start).take(pc + 4 - start) {
+            let marker = if Some(i) == self.machine.current() { "=>" } else if self.breakpoints.contains(&i) { " *" } else { "  " };
+            writeln!(out, "{} {:>5}: {}", marker, i, op)?;
+        }
+        Ok(())
     }
 
-    fn run_till_end_fixed(self) -> i32 {
-        let mut found = false;
-        let mut ret = 0;
-        let program = self.program.clone();
+    fn repl<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        write!(out, "(day8) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let arg = words.next();
+            let pc_arg = || arg.and_then(|a| a.parse::<usize>().ok());
+            match command {
+                "" => (),
+                "s" | "step" => {
+                    let count = pc_arg().unwrap_or(1);
+                    for _ in 0..count {
+                        if !self.step(out, true)? {
+                            break;
+                        }
+                    }
+                },
+                "c" | "continue" => self.cont(out)?,
+                "b" | "break" => match pc_arg() {
+                    Some(pc) => { self.breakpoints.insert(pc); },
+                    None => writeln!(out, "breakpoints: {:?}", self.breakpoints)?,
+                },
+                "d" | "delete" => match pc_arg() {
+                    Some(pc) => { self.breakpoints.remove(&pc); },
+                    None => self.breakpoints.clear(),
+                },
+                "w" | "watch" => {
+                    self.watch = match arg.map(|a| a.parse::<i32>()) {
+                        Some(Ok(x)) => Some(Watch::Equals(x)),
+                        Some(Err(_)) => {
+                            writeln!(out, "watch takes an optional accumulator value")?;
+                            self.watch
+                        },
+                        None => Some(Watch::Changed),
+                    };
+                },
+                "unwatch" => self.watch = None,
+                "t" | "trace" => {
+                    self.trace = arg != Some("off");
+                    writeln!(out, "trace {}", if self.trace { "on" } else { "off" })?;
+                },
+                "p" | "print" => writeln!(out, "pc {}, acc {}, steps {}", self.machine.pc, self.machine.acc, self.machine.steps)?,
+                "l" | "list" => self.list(out)?,
+                "q" | "quit" => return Ok(()),
+                _ => writeln!(out, "commands: step [n], continue, break [pc], delete [pc], watch [acc], unwatch, trace [off], print, list, quit")?,
+            }
+            write!(out, "(day8) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+}
 
-        for op in 0..self.program.len() {
-            let mut new_machine = MachineState::new(program.clone());
-            new_machine.flip_instruction(op);
-            if let Ok(acc) = new_machine.run_till_end() {
-                ret = acc;
-                found = true;
-                break
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum BlockExit {
+    Block(usize),
+    End,
+    OutOfRange(i64),
+}
+
+#[derive(Debug, Clone)]
+struct BasicBlock {
+    start : usize,
+    end : usize,
+    exit : BlockExit,
+}
+
+struct ControlFlowGraph<'a> {
+    program : &'a [Operation],
+    blocks : Vec<BasicBlock>,
+    block_of : Vec<usize>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    fn new(program: &'a [Operation]) -> Self {
+        let n = program.len();
+        let mut leader = vec![false; n + 1];
+        leader[0] = true;
+        for (pc, op) in program.iter().enumerate() {
+            if let Operation::Jmp(_) = op {
+                leader[pc + 1] = true;
+                let target = op.successor(pc);
+                if target >= 0 && (target as usize) < n {
+                    leader[target as usize] = true;
+                }
             }
         }
 
-        if found {
-            ret
-        } else {
-            panic!("Still haven't found a single flippable instruction")
+        let mut block_of = vec![0; n];
+        let mut starts = vec![];
+        for pc in 0..n {
+            if leader[pc] {
+                starts.push(pc);
+            }
+            block_of[pc] = starts.len() - 1;
+        }
+
+        let blocks = starts.iter()
+                           .enumerate()
+                           .map(|(b, &start)| {
+                               let end = *starts.get(b + 1).unwrap_or(&n);
+                               let next = program[end - 1].successor(end - 1);
+                               let exit = if next == n as i64 {
+                                   BlockExit::End
+                               } else if next < 0 || next > n as i64 {
+                                   BlockExit::OutOfRange(next)
+                               } else {
+                                   BlockExit::Block(block_of[next as usize])
+                               };
+                               BasicBlock { start, end, exit }
+                           })
+                           .collect();
+
+        Self { program, blocks, block_of }
+    }
+
+    fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut curr = 0;
+        while !self.blocks.is_empty() && !seen[curr] {
+            seen[curr] = true;
+            match self.blocks[curr].exit {
+                BlockExit::Block(next) => curr = next,
+                _ => break,
+            }
+        }
+        seen
+    }
+
+    fn unreachable_instructions(&self) -> Vec<usize> {
+        let reachable = self.reachable();
+        (0..self.program.len()).filter(|&pc| !reachable[self.block_of[pc]]).collect()
+    }
+
+    // (instruction, target) for every jump landing neither in the program nor just past it
+    fn out_of_range_jumps(&self) -> Vec<(usize, i64)> {
+        self.program.iter()
+                    .enumerate()
+                    .map(|(pc, op)| (pc, op.successor(pc)))
+                    .filter(|&(_, target)| target < 0 || target > self.program.len() as i64)
+                    .collect()
+    }
+
+    // every block has exactly one successor, so each cycle is found by
+    // walking forward until a block repeats
+    fn loops(&self) -> Vec<Vec<usize>> {
+        const UNSEEN : usize = usize::MAX;
+        let mut walk_of = vec![UNSEEN; self.blocks.len()];
+        let mut loops = vec![];
+        for start in 0..self.blocks.len() {
+            let mut path = vec![];
+            let mut curr = start;
+            while walk_of[curr] == UNSEEN {
+                walk_of[curr] = start;
+                path.push(curr);
+                match self.blocks[curr].exit {
+                    BlockExit::Block(next) => curr = next,
+                    _ => break,
+                }
+            }
+            if walk_of[curr] == start {
+                if let Some(pos) = path.iter().position(|&b| b == curr) {
+                    if matches!(self.blocks[*path.last().unwrap()].exit, BlockExit::Block(_)) {
+                        loops.push(path.split_off(pos));
+                    }
+                }
+            }
+        }
+        loops
+    }
+
+    fn entry_loop(&self) -> Option<Vec<usize>> {
+        let reachable = self.reachable();
+        self.loops().into_iter().find(|l| reachable[l[0]])
+    }
+
+    fn to_dot(&self) -> String {
+        let reachable = self.reachable();
+        let in_loop : Vec<usize> = self.loops().into_iter().flatten().collect();
+        let mut dot = String::from("digraph program {\n    node [shape=box, fontname=monospace];\n");
+        for (b, block) in self.blocks.iter().enumerate() {
+            let body : String = (block.start..block.end)
+                .map(|pc| format!("{}: {}\\l", pc, self.program[pc]))
+                .collect();
+            let style = if !reachable[b] {
+                ", style=dashed, color=grey"
+            } else if in_loop.contains(&b) {
+                ", color=red"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    b{} [label=\"{}\"{}];\n", b, body, style));
+            match block.exit {
+                BlockExit::Block(next) => dot.push_str(&format!("    b{} -> b{};\n", b, next)),
+                BlockExit::End => dot.push_str(&format!("    b{} -> end;\n", b)),
+                BlockExit::OutOfRange(target) => {
+                    dot.push_str(&format!("    out{} [label=\"pc {}\", shape=octagon, color=red];\n", b, target));
+                    dot.push_str(&format!("    b{} -> out{};\n", b, b));
+                },
+            }
         }
+        dot.push_str("    end [shape=doublecircle];\n}\n");
+        dot
     }
 }
 
@@ -112,14 +664,83 @@ fn main() {
     jmp -4
     acc +6";
 
-    let test_program = parse_program(test_input);
+    let test_program = assemble(test_input).expect("valid program");
     println!("{:?}", test_program);
 
-    println!("val of test program is {}", (MachineState::new(test_program)).run_till_end().expect_err("Did not loop"));
+    match MachineState::new(&test_program).run_till_end() {
+        Termination::InfiniteLoop(state) => println!("val of test program is {}", state.acc),
+        other => panic!("Did not loop: {:?}", other),
+    }
+    for source in &["acc +2\njmp -5", "nop +0\njmp +7", "acc +1\nacc +2"] {
+        let program = assemble(source).expect("valid program");
+        let end = MachineState::new(&program).run_till_end();
+        println!("{:?} finishes with {:?}", disassemble(&program), end);
+    }
+
+    let labelled = "\
+    start:  nop 0        # same as the test program above
+            acc +1
+            jmp skip
+    back:   acc +3
+            JMP   -3
+            acc -99
+    skip:   acc +1 ; loops back
+            jmp back
+            acc +6";
+    assert_eq!(assemble(labelled), Ok(test_program.clone()));
+    print!("{}", disassemble(&test_program));
+    match assemble("jmp nowhere") {
+        Ok(_) => println!("assembled a jump to nowhere"),
+        Err(e) => println!("{}", e),
+    }
+
+    let cfg = ControlFlowGraph::new(&test_program);
+    println!("test program has {} blocks, unreachable instructions {:?}, out of range jumps {:?}, entry loop {:?}",
+             cfg.blocks.len(), cfg.unreachable_instructions(), cfg.out_of_range_jumps(), cfg.entry_loop());
+    std::fs::write("day8.dot", cfg.to_dot()).expect("write day8.dot");
+
+    let mut machine = MachineState::with_halt(&test_program, HaltConditions { on_loop: false, step_limit: Some(20) });
+    let mut executed = 0;
+    let reason = loop {
+        match machine.step() {
+            Event::Executed { .. } => executed += 1,
+            Event::Halted(reason) => break reason,
+        }
+    };
+    println!("test program without loop detection ran {} steps, halted by {:?} with acc {}", executed, reason, machine.acc);
 
     let real_input = include_str!("day8.txt");
-    let real_program = parse_program(real_input);
-    println!("val of real program is {}", (MachineState::new(real_program.clone())).run_till_end().expect_err("Did not loop"));
+    let real_program = assemble(real_input).expect("valid program");
+    if std::env::args().nth(1).as_deref() == Some("debug") {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        Debugger::new(MachineState::new(&real_program)).repl(stdin.lock(), &mut stdout.lock()).expect("terminal io");
+        return;
+    }
+    let listing = disassemble(&real_program);
+    assert_eq!(assemble(&listing), Ok(real_program.clone()));
+    assert_eq!(disassemble(&assemble(&listing).expect("valid program")), listing);
+    let canonical : Vec<&str> = real_input.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+    assert_eq!(listing.lines().collect::<Vec<_>>(), canonical);
+    println!("real program round-trips through the disassembler");
+    match MachineState::new(&real_program).run_till_end() {
+        Termination::InfiniteLoop(state) => println!("val of real program is {}", state.acc),
+        other => panic!("Did not loop: {:?}", other),
+    }
+
+    let repair = find_repair(&real_program).expect("a single flip fixes the program");
+    let mut patched = real_program.clone();
+    patched[repair.index] = repair.replacement;
+    let end = MachineState::new(&patched).run_till_end();
+    assert!(matches!(end, Termination::NormalEnd(_)));
+    assert_eq!(end.state().acc, repair.acc);
+    println!("fixed val of real program is {} after changing {} at {} to {}",
+             repair.acc, real_program[repair.index], repair.index, repair.replacement);
 
-    println!("fixed val of real program is {}", (MachineState::new(real_program.clone())).run_till_end_fixed());
-}
\ No newline at end of file
+    // both already end, so flipping anything could only break them
+    for source in ["nop +0\nacc +1", "acc +1\njmp +2\nacc +5\nnop -1"] {
+        let program = assemble(source).expect("valid program");
+        assert!(matches!(MachineState::new(&program).run_till_end(), Termination::NormalEnd(_)));
+        assert_eq!(find_repair(&program), None);
+    }
+}
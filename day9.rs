@@ -1,47 +1,180 @@
-fn is_valid(num: i64, previous: &[i64]) -> bool {
-    previous.iter()
-            .flat_map(|i| previous.iter().map(move |j| (i,j)))
-            .find_map(|(i,j)| {
-                if (i != j) && (i + j == num) {
-                    Some(true)
-                } else {
-                    None
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead};
+
+// counts of every sum of two different numbers in the last `preamble`
+// numbers, kept up to date as numbers enter and leave the window
+struct XmasWindow {
+    preamble : usize,
+    window : VecDeque<i64>,
+    sums : HashMap<i64, usize>,
+}
+
+impl XmasWindow {
+    fn new(preamble: usize) -> Self {
+        Self { preamble, window: VecDeque::with_capacity(preamble + 1), sums: HashMap::new() }
+    }
+
+    fn is_full(&self) -> bool {
+        self.window.len() == self.preamble
+    }
+
+    fn is_valid(&self, num: i64) -> bool {
+        self.sums.get(&num).is_some_and(|&c| c > 0)
+    }
+
+    fn push(&mut self, num: i64) {
+        for &other in self.window.iter() {
+            if other != num {
+                *self.sums.entry(num + other).or_insert(0) += 1;
+            }
+        }
+        self.window.push_back(num);
+        if self.window.len() > self.preamble {
+            let old = self.window.pop_front().unwrap();
+            for &other in self.window.iter() {
+                if other != old {
+                    let count = self.sums.get_mut(&(old + other)).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        self.sums.remove(&(old + other));
+                    }
                 }
-            })
-            .unwrap_or(false)
+            }
+        }
+    }
+
+    // Some(valid) once the preamble has been read, None before that
+    fn check_and_push(&mut self, num: i64) -> Option<bool> {
+        let verdict = if self.is_full() { Some(self.is_valid(num)) } else { None };
+        self.push(num);
+        verdict
+    }
+}
+
+fn all_invalid(sequence: &[i64], trailing: usize) -> Vec<(usize, i64)> {
+    let mut window = XmasWindow::new(trailing);
+    sequence.iter()
+            .enumerate()
+            .filter(|&(_, &num)| window.check_and_push(num) == Some(false))
+            .map(|(i, &num)| (i, num))
+            .collect()
 }
 
 fn first_invalid(sequence: &[i64], trailing: usize) -> Option<i64> {
-    for i in trailing..sequence.len() {
-        let previous = &sequence[i-trailing..i];
-        if !is_valid(sequence[i], previous) {
-            return Some(sequence[i])
+    let mut window = XmasWindow::new(trailing);
+    sequence.iter()
+            .copied()
+            .find(|&num| window.check_and_push(num) == Some(false))
+}
+
+// inclusive (start, end) of the first run of at least two numbers adding
+// up to target
+fn find_cont_sum(target: i64, sequence: &[i64]) -> Option<(usize, usize)> {
+    if sequence.iter().any(|&x| x < 0) {
+        return find_cont_sum_prefix(target, sequence);
+    }
+    let mut start = 0;
+    let mut total = 0;
+    for (end, &num) in sequence.iter().enumerate() {
+        total += num;
+        while total > target && start < end {
+            total -= sequence[start];
+            start += 1;
+        }
+        if total == target && start < end {
+            return Some((start, end));
         }
     }
     None
 }
 
-fn find_cont_sum<'a>(target: i64, sequence: &'a [i64]) -> &'a [i64] {
-    for i in 0..sequence.len() {
-        let mut j = i;
-        let mut total = 0;
-        while total < target {
-            total += sequence[j];
-            if total == target {
-                return &sequence[i..j];
-            }
-            j += 1;
+// two pointers only work while sums grow monotonically, with negative
+// numbers look up earlier prefix sums instead
+fn find_cont_sum_prefix(target: i64, sequence: &[i64]) -> Option<(usize, usize)> {
+    let mut first_prefix : HashMap<i64, usize> = HashMap::new();
+    let mut prefix = vec![0];
+    for &num in sequence {
+        prefix.push(prefix.last().unwrap() + num);
+    }
+    for end in 1..sequence.len() {
+        first_prefix.entry(prefix[end - 1]).or_insert(end - 1);
+        if let Some(&start) = first_prefix.get(&(prefix[end + 1] - target)) {
+            return Some((start, end));
         }
     }
-    return &sequence[0..0];
+    None
+}
+
+fn weakness_in(target: i64, sequence: &[i64]) -> Option<i64> {
+    let (start, end) = find_cont_sum(target, sequence)?;
+    let cont_seq = &sequence[start..=end];
+    let min = cont_seq.iter().min()?;
+    let max = cont_seq.iter().max()?;
+    Some(min + max)
 }
 
 fn find_weakness(sequence: &[i64], trailing: usize) -> i64 {
     let invalid = first_invalid(sequence, trailing).expect("no invalid");
-    let cont_seq = find_cont_sum(invalid, sequence);
-    let min = cont_seq.iter().min().expect("some sequence");
-    let max = cont_seq.iter().max().expect("some sequence");
-    min + max
+    weakness_in(invalid, sequence).expect("some sequence")
+}
+
+// reads one number per line and yields (index, number) for every invalid
+// number as soon as it arrives, keeping at most `history` numbers around
+// for the weakness search
+struct XmasStream<R> {
+    lines : io::Lines<R>,
+    window : XmasWindow,
+    history : VecDeque<i64>,
+    history_limit : usize,
+    seen : usize,
+}
+
+impl<R: BufRead> XmasStream<R> {
+    fn new(reader: R, trailing: usize, history_limit: usize) -> Self {
+        Self {
+            lines: reader.lines(),
+            window: XmasWindow::new(trailing),
+            history: VecDeque::new(),
+            history_limit,
+            seen: 0,
+        }
+    }
+
+    // None until some run of the retained history adds up to target
+    fn weakness(&mut self, target: i64) -> Option<i64> {
+        weakness_in(target, self.history.make_contiguous())
+    }
+}
+
+impl<R: BufRead> Iterator for XmasStream<R> {
+    type Item = io::Result<(usize, i64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in &mut self.lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let num = match line.parse::<i64>() {
+                Ok(num) => num,
+                Err(e) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, e))),
+            };
+            let index = self.seen;
+            self.seen += 1;
+            while self.history.len() >= self.history_limit.max(1) {
+                self.history.pop_front();
+            }
+            self.history.push_back(num);
+            if self.window.check_and_push(num) == Some(false) {
+                return Some(Ok((index, num)));
+            }
+        }
+        None
+    }
 }
 
 fn parse_input(input: &str) -> Vec<i64> {
@@ -73,13 +206,42 @@ fn main() {
     309
     576";
 
-    println!("is valid {:?}", is_valid(10, &[1,2,6,3,4,5]));
+    let mut window = XmasWindow::new(6);
+    for &num in &[1,2,6,3,4,5] {
+        window.push(num);
+    }
+    println!("is valid {:?}", window.is_valid(10));
+    let mut stream = XmasStream::new(test_input.as_bytes(), 5, 1000);
+    while let Some(invalid) = stream.next() {
+        let (index, num) = invalid.expect("int input");
+        println!("streamed invalid {} at {}, weakness so far {:?}", num, index, stream.weakness(num));
+    }
+
     let test_input = parse_input(&test_input);
     println!("first invalid in test: {}", first_invalid(&test_input, 5).expect("no invalid"));
+    println!("all invalid in test: {:?}", all_invalid(&test_input, 5));
 
     let real_input = parse_input(&include_str!("day9.txt"));
     println!("first invalid in real: {}", first_invalid(&real_input, 25).expect("no invalid"));
+    println!("invalid count in real: {}", all_invalid(&real_input, 25).len());
 
     println!("weakness in test: {}", find_weakness(&test_input, 5));
+    println!("range for 127 in test: {:?}", find_cont_sum(127, &test_input));
+    println!("range for 4 in [4, 1, 3]: {:?}", find_cont_sum(4, &[4, 1, 3]));
+    println!("range for 3 in [5, -2, 7, -4]: {:?}", find_cont_sum(3, &[5, -2, 7, -4]));
     println!("weakness in real: {}", find_weakness(&real_input, 25));
+
+    if std::env::args().nth(1).as_deref() == Some("stream") {
+        let stdin = io::stdin();
+        let mut stream = XmasStream::new(stdin.lock(), 25, 100_000);
+        let mut first = None;
+        for invalid in &mut stream {
+            let (index, num) = invalid.expect("int input");
+            println!("invalid {} at {}", num, index);
+            first = first.or(Some(num));
+        }
+        if let Some(num) = first {
+            println!("weakness for {} is {:?}", num, stream.weakness(num));
+        }
+    }
 }
\ No newline at end of file